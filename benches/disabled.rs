@@ -0,0 +1,32 @@
+//! Cost of capturing on a disabled client, which libraries pay on every call
+//! when they ship Sentry hooks unconditionally. Run with `cargo bench`.
+
+#[macro_use]
+extern crate sentry;
+
+use std::time::Instant;
+
+use sentry::{MessageOptions, Sentry};
+
+const ITERATIONS: u32 = 1_000_000;
+
+fn bench<F: FnMut()>(name: &str, mut f: F) {
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        f();
+    }
+    let elapsed = start.elapsed();
+    let nanos = elapsed.as_secs() * 1_000_000_000 + elapsed.subsec_nanos() as u64;
+    println!("{:<24} {:>8.1} ns/iter", name, nanos as f64 / ITERATIONS as f64);
+}
+
+fn main() {
+    let sentry = Sentry::disabled();
+    let options = MessageOptions::default();
+
+    bench("error", || sentry.error("bench", "Something failed", Some("bench.rs")));
+    bench("capture_message", || sentry.capture_message("Something failed", &options));
+    bench("capture_message!", || capture_message!(sentry, options, "{} failed", "something"));
+    bench("monitor_job (error)",
+          || { let _ = sentry.monitor_job("bench", || Err::<(), _>("failed")); });
+}
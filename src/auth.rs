@@ -0,0 +1,222 @@
+//! The `X-Sentry-Auth` header authenticating every request.
+
+use std::borrow::Cow;
+use std::fmt;
+use std::str::FromStr;
+
+#[cfg(feature = "hyper-transport")]
+use hyper;
+
+use errors::*;
+
+/// Protocol version this client speaks.
+pub const PROTOCOL_VERSION: u16 = 7;
+
+/// Value of the `X-Sentry-Auth` header, ex:
+/// `Sentry sentry_version=7, sentry_client=rust-sentry/0.1.10, sentry_timestamp=1501581600, sentry_key=abc`
+///
+/// `sentry_secret` is left out when the credential has none, and values that
+/// aren't plain tokens are quoted. Parsing one, ex: in a custom transport or a
+/// test, gives an `AuthHeader<'static>`; with the `hyper-transport` feature it
+/// is also a typed hyper header.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AuthHeader<'a> {
+    version: u16,
+    client: Cow<'a, str>,
+    timestamp: Option<i64>,
+    key: Cow<'a, str>,
+    secret: Option<Cow<'a, str>>,
+}
+
+impl<'a> AuthHeader<'a> {
+    /// The name of the header.
+    pub const NAME: &'static str = "X-Sentry-Auth";
+
+    /// An empty `secret` is treated as absent.
+    pub fn new(client: &'a str, key: &'a str, secret: &'a str) -> AuthHeader<'a> {
+        AuthHeader {
+            version: PROTOCOL_VERSION,
+            client: Cow::Borrowed(client),
+            timestamp: Some(::time::get_time().sec),
+            key: Cow::Borrowed(key),
+            secret: if secret.is_empty() { None } else { Some(Cow::Borrowed(secret)) },
+        }
+    }
+
+    pub fn with_timestamp(mut self, timestamp: i64) -> AuthHeader<'a> {
+        self.timestamp = Some(timestamp);
+        self
+    }
+
+    /// The `sentry_version`, the protocol version of the client.
+    pub fn version(&self) -> u16 {
+        self.version
+    }
+
+    /// The `sentry_client`, ex: "rust-sentry/0.1.10".
+    pub fn client(&self) -> &str {
+        &self.client
+    }
+
+    /// The `sentry_timestamp`, in seconds since the epoch, which newer
+    /// clients leave out.
+    pub fn timestamp(&self) -> Option<i64> {
+        self.timestamp
+    }
+
+    /// The `sentry_key`, the public key of the DSN.
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    /// The `sentry_secret`, only set with legacy DSNs.
+    pub fn secret(&self) -> Option<&str> {
+        self.secret.as_ref().map(|secret| &**secret)
+    }
+
+    pub fn into_owned(self) -> AuthHeader<'static> {
+        AuthHeader {
+            version: self.version,
+            client: Cow::Owned(self.client.into_owned()),
+            timestamp: self.timestamp,
+            key: Cow::Owned(self.key.into_owned()),
+            secret: self.secret.map(|secret| Cow::Owned(secret.into_owned())),
+        }
+    }
+}
+
+impl<'a> fmt::Display for AuthHeader<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f,
+               "Sentry sentry_version={}, sentry_client={}",
+               self.version,
+               Value(&self.client))?;
+        if let Some(timestamp) = self.timestamp {
+            write!(f, ", sentry_timestamp={}", timestamp)?;
+        }
+        write!(f, ", sentry_key={}", Value(&self.key))?;
+        if let Some(ref secret) = self.secret {
+            write!(f, ", sentry_secret={}", Value(secret))?;
+        }
+        Ok(())
+    }
+}
+
+/// Parses the header as sent by any client: `sentry_version`, `sentry_client`
+/// and `sentry_key` are required, unknown parameters are ignored.
+impl FromStr for AuthHeader<'static> {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<AuthHeader<'static>> {
+        let s = s.trim();
+        if !s.get(..7).map_or(false, |scheme| scheme.eq_ignore_ascii_case("Sentry ")) {
+            bail!(ErrorKind::InvalidAuthHeader("no \"Sentry\" scheme".to_string()));
+        }
+        let (mut version, mut client, mut timestamp, mut key, mut secret) = (None, None, None, None, None);
+        for (name, value) in parameters(&s[7..])? {
+            match &*name {
+                "sentry_version" => {
+                    version = Some(value.parse::<u16>()
+                        .map_err(|_| ErrorKind::InvalidAuthHeader(format!("version {:?} is not a number", value)))?)
+                }
+                "sentry_timestamp" => {
+                    // some clients send fractional seconds
+                    let seconds = value.parse::<f64>()
+                        .map_err(|_| ErrorKind::InvalidAuthHeader(format!("timestamp {:?} is not a number", value)))?;
+                    timestamp = Some(seconds as i64)
+                }
+                "sentry_client" => client = Some(value),
+                "sentry_key" => key = Some(value),
+                "sentry_secret" if !value.is_empty() => secret = Some(value),
+                _ => {}
+            }
+        }
+        let missing = |name: &str| ErrorKind::InvalidAuthHeader(format!("no {}", name));
+        Ok(AuthHeader {
+            version: version.ok_or_else(|| missing("sentry_version"))?,
+            client: Cow::Owned(client.ok_or_else(|| missing("sentry_client"))?),
+            timestamp: timestamp,
+            key: Cow::Owned(key.ok_or_else(|| missing("sentry_key"))?),
+            secret: secret.map(Cow::Owned),
+        })
+    }
+}
+
+#[cfg(feature = "hyper-transport")]
+impl hyper::header::Header for AuthHeader<'static> {
+    fn header_name() -> &'static str {
+        AuthHeader::NAME
+    }
+
+    fn parse_header(raw: &hyper::header::Raw) -> hyper::Result<AuthHeader<'static>> {
+        raw.one()
+            .and_then(|line| ::std::str::from_utf8(line).ok())
+            .and_then(|line| line.parse().ok())
+            .ok_or(hyper::Error::Header)
+    }
+
+    fn fmt_header(&self, f: &mut hyper::header::Formatter) -> fmt::Result {
+        f.fmt_line(self)
+    }
+}
+
+/// The comma-separated `name=value` pairs of the header, values unquoted.
+fn parameters(s: &str) -> Result<Vec<(String, String)>> {
+    let mut parameters = Vec::new();
+    let mut chars = s.chars().peekable();
+    loop {
+        while chars.peek().map_or(false, |&c| c == ',' || c.is_whitespace()) {
+            chars.next();
+        }
+        if chars.peek().is_none() {
+            return Ok(parameters);
+        }
+        let name: String = chars.by_ref().take_while(|&c| c != '=').collect();
+        let mut value = String::new();
+        if chars.peek() == Some(&'"') {
+            chars.next();
+            loop {
+                match chars.next() {
+                    Some('"') => break,
+                    Some('\\') => value.extend(chars.next()),
+                    Some(c) => value.push(c),
+                    None => bail!(ErrorKind::InvalidAuthHeader(format!("unterminated value of {}", name.trim()))),
+                }
+            }
+        } else {
+            while let Some(&c) = chars.peek() {
+                if c == ',' {
+                    break;
+                }
+                value.push(c);
+                chars.next();
+            }
+        }
+        parameters.push((name.trim().to_string(), value.trim().to_string()));
+    }
+}
+
+/// A header parameter value, quoted unless it's a plain token.
+struct Value<'a>(&'a str);
+
+impl<'a> fmt::Display for Value<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let plain = !self.0.is_empty() &&
+                    self.0.chars().all(|c| c.is_ascii() && !c.is_whitespace() && !c.is_control() && !is_separator(c));
+        if plain {
+            return f.write_str(self.0);
+        }
+        f.write_str("\"")?;
+        for c in self.0.chars() {
+            if c == '"' || c == '\\' {
+                f.write_str("\\")?;
+            }
+            write!(f, "{}", c)?;
+        }
+        f.write_str("\"")
+    }
+}
+
+fn is_separator(c: char) -> bool {
+    c == '"' || c == ',' || c == '=' || c == '\\'
+}
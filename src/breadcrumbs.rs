@@ -0,0 +1,229 @@
+//! Trail of events leading up to a capture, kept per scope.
+
+use std::collections::{HashMap, VecDeque};
+use std::time::Duration;
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use timestamp::{self, Timestamp};
+
+/// Breadcrumbs a scope keeps unless configured otherwise.
+pub const DEFAULT_MAX_BREADCRUMBS: usize = 100;
+
+/// Bytes of a body kept in an HTTP breadcrumb, see `Settings::http_bodies`.
+pub const MAX_HTTP_BODY_LENGTH: usize = 1024;
+
+/// How Sentry renders a breadcrumb and interprets its `data`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BreadcrumbType {
+    Default,
+    /// `data` holds `url`, `method`, `status_code` and `reason`.
+    Http,
+    Query,
+    /// `data` holds `from` and `to`.
+    Navigation,
+}
+
+impl Default for BreadcrumbType {
+    fn default() -> BreadcrumbType {
+        BreadcrumbType::Default
+    }
+}
+
+// see https://docs.sentry.io/clientdev/interfaces/breadcrumbs/
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Breadcrumb {
+    #[serde(with = "timestamp")]
+    timestamp: Timestamp,
+    #[serde(rename = "type", default)]
+    ty: BreadcrumbType,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    category: Option<String>, // ex: "sql.query" or "ui.click"
+    #[serde(default = "default_level")]
+    level: String, // fatal, error, warning, info, debug
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    message: String,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    data: HashMap<String, String>,
+}
+
+fn default_level() -> String {
+    "info".to_string()
+}
+
+impl Breadcrumb {
+    pub fn new(message: &str) -> Breadcrumb {
+        Breadcrumb {
+            timestamp: timestamp::now(),
+            ty: BreadcrumbType::Default,
+            category: None,
+            level: default_level(),
+            message: message.to_owned(),
+            data: hashmap!{},
+        }
+    }
+
+    /// A request handled by a server, for middlewares to record on the scope
+    /// of the worker once it completes, so events show the preceding requests.
+    /// Server errors are recorded at `error` level, client errors at `warning`.
+    pub fn request(method: &str, route: &str, status: u16, duration: Duration) -> Breadcrumb {
+        Breadcrumb::http_exchange("request", method, route, status, duration)
+    }
+
+    /// A call to another service made by an HTTP client, for its integration
+    /// to record with `Sentry::add_http_breadcrumb`. Levels are set like
+    /// those of `request`.
+    pub fn http(method: &str, url: &str, status: u16, duration: Duration) -> Breadcrumb {
+        Breadcrumb::http_exchange("http", method, url, status, duration)
+    }
+
+    fn http_exchange(category: &str, method: &str, url: &str, status: u16, duration: Duration) -> Breadcrumb {
+        let mut breadcrumb = Breadcrumb::new("");
+        breadcrumb.ty = BreadcrumbType::Http;
+        breadcrumb.category = Some(category.to_string());
+        breadcrumb.level = if status >= 500 {
+                "error"
+            } else if status >= 400 {
+                "warning"
+            } else {
+                "info"
+            }
+            .to_string();
+        breadcrumb.data = hashmap!{
+            "method".to_string() => method.to_string(),
+            "url".to_string() => url.to_string(),
+            "status_code".to_string() => status.to_string(),
+            "duration_ms".to_string() =>
+                (duration.as_secs() * 1000 + duration.subsec_nanos() as u64 / 1_000_000).to_string(),
+        };
+        breadcrumb
+    }
+
+    pub fn set_ty(&mut self, ty: BreadcrumbType) {
+        self.ty = ty;
+    }
+
+    pub fn set_category(&mut self, category: Option<&str>) {
+        self.category = category.map(|c| c.to_owned());
+    }
+
+    pub fn set_level(&mut self, level: &str) {
+        self.level = level.to_owned();
+    }
+
+    pub fn set_data(&mut self, key: String, value: String) {
+        self.data.insert(key, value);
+    }
+
+    pub fn set_timestamp(&mut self, timestamp: Timestamp) {
+        self.timestamp = timestamp;
+    }
+
+    pub fn ty(&self) -> BreadcrumbType {
+        self.ty
+    }
+
+    pub fn category(&self) -> Option<&str> {
+        self.category.as_ref().map(|c| &c[..])
+    }
+
+    pub fn level(&self) -> &str {
+        &self.level
+    }
+
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    pub fn data(&self) -> &HashMap<String, String> {
+        &self.data
+    }
+
+    pub fn data_mut(&mut self) -> &mut HashMap<String, String> {
+        &mut self.data
+    }
+
+    pub fn timestamp(&self) -> &Timestamp {
+        &self.timestamp
+    }
+}
+
+/// Fixed-capacity ring buffer of breadcrumbs, evicting the oldest ones so
+/// long-running processes don't grow unbounded context.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Breadcrumbs {
+    values: VecDeque<Breadcrumb>,
+    capacity: usize,
+}
+
+impl Default for Breadcrumbs {
+    fn default() -> Breadcrumbs {
+        Breadcrumbs::with_capacity(DEFAULT_MAX_BREADCRUMBS)
+    }
+}
+
+impl Breadcrumbs {
+    pub fn with_capacity(capacity: usize) -> Breadcrumbs {
+        Breadcrumbs {
+            values: VecDeque::with_capacity(capacity),
+            capacity: capacity,
+        }
+    }
+
+    pub fn push(&mut self, breadcrumb: Breadcrumb) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.values.len() == self.capacity {
+            self.values.pop_front();
+        }
+        self.values.push_back(breadcrumb);
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Changes the capacity, evicting the oldest breadcrumbs beyond it.
+    pub fn set_capacity(&mut self, capacity: usize) {
+        while self.values.len() > capacity {
+            self.values.pop_front();
+        }
+        self.capacity = capacity;
+    }
+
+    pub fn clear(&mut self) {
+        self.values.clear();
+    }
+
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    /// Oldest first.
+    pub fn iter<'a>(&'a self) -> ::std::collections::vec_deque::Iter<'a, Breadcrumb> {
+        self.values.iter()
+    }
+}
+
+/// Breadcrumbs are sent as `{"values": [...]}`, oldest first.
+pub fn serialize<S: Serializer>(breadcrumbs: &[Breadcrumb], serializer: S) -> Result<S::Ok, S::Error> {
+    #[derive(Serialize)]
+    struct Values<'a> {
+        values: &'a [Breadcrumb],
+    }
+    Values { values: breadcrumbs }.serialize(serializer)
+}
+
+pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<Breadcrumb>, D::Error> {
+    #[derive(Deserialize)]
+    struct Values {
+        values: Vec<Breadcrumb>,
+    }
+    Values::deserialize(deserializer).map(|v| v.values)
+}
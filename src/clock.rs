@@ -0,0 +1,124 @@
+//! The source of time of a client, replaceable so tests get reproducible
+//! payloads and can step through rate limit windows without sleeping.
+
+use std::sync::{Arc, Mutex, MutexGuard, RwLock};
+use std::time::{Duration, Instant};
+
+use timestamp::{self, Timestamp};
+
+/// Tells the time, for event timestamps (`now`) and for measuring rate limit
+/// windows (`instant`).
+pub trait Clock: Send + Sync {
+    fn now(&self) -> Timestamp;
+    fn instant(&self) -> Instant;
+}
+
+/// The system clock, the default.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Timestamp {
+        timestamp::now()
+    }
+
+    fn instant(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+/// A clock standing still until told otherwise:
+///
+/// ```
+/// # use std::sync::Arc;
+/// # use std::time::Duration;
+/// # use sentry::{ManualClock, Sentry};
+/// let clock = Arc::new(ManualClock::new(1501588800)); // 2017-08-01T12:00:00
+/// let sentry = Sentry::disabled();
+/// sentry.set_clock(clock.clone());
+/// // events are timestamped 2017-08-01T12:00:00, and a 60 second Retry-After
+/// // only ends once the clock advanced that far
+/// clock.advance(Duration::from_secs(60));
+/// ```
+pub struct ManualClock {
+    start: Instant,
+    state: Mutex<(Timestamp, Duration)>, // the time, and how far it moved since start
+}
+
+impl ManualClock {
+    /// A clock at `unix_time`, in seconds since 1970-01-01T00:00:00 UTC.
+    ///
+    /// # Panics
+    ///
+    /// If `unix_time` is out of the range of `Timestamp`.
+    pub fn new(unix_time: i64) -> ManualClock {
+        let now = timestamp::from_unix(unix_time).expect("unix_time out of the range of Timestamp");
+        ManualClock {
+            start: Instant::now(),
+            state: Mutex::new((now, Duration::from_secs(0))),
+        }
+    }
+
+    /// Moves the clock forward by `by`.
+    pub fn advance(&self, by: Duration) {
+        let mut state = lock(&self.state);
+        state.0 = timestamp::add(&state.0, by);
+        state.1 += by;
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> Timestamp {
+        lock(&self.state).0
+    }
+
+    fn instant(&self) -> Instant {
+        self.start + lock(&self.state).1
+    }
+}
+
+fn lock(state: &Mutex<(Timestamp, Duration)>) -> MutexGuard<'_, (Timestamp, Duration)> {
+    match state.lock() {
+        Ok(guard) => guard,
+        Err(poisoned) => poisoned.into_inner(),
+    }
+}
+
+/// The clock of a client, shared by its events and its transport so
+/// `Sentry::set_clock` reaches both.
+#[derive(Clone)]
+pub struct SharedClock(Arc<RwLock<Arc<Clock>>>);
+
+impl SharedClock {
+    pub fn new() -> SharedClock {
+        SharedClock(Arc::new(RwLock::new(Arc::new(SystemClock))))
+    }
+
+    pub fn set(&self, clock: Arc<Clock>) {
+        match self.0.write() {
+            Ok(mut guard) => *guard = clock,
+            Err(poisoned) => *poisoned.into_inner() = clock,
+        }
+    }
+
+    fn get(&self) -> Arc<Clock> {
+        match self.0.read() {
+            Ok(guard) => guard.clone(),
+            Err(poisoned) => poisoned.into_inner().clone(),
+        }
+    }
+
+    pub fn now(&self) -> Timestamp {
+        self.get().now()
+    }
+
+    pub fn instant(&self) -> Instant {
+        self.get().instant()
+    }
+}
+
+impl ::std::fmt::Debug for SharedClock {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+        f.write_str("SharedClock")
+    }
+}
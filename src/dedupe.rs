@@ -0,0 +1,115 @@
+//! Suppresses repeats of an event, reporting them as a single summary.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use clock::SharedClock;
+use delivery::Tracker;
+use {uuid, Event};
+
+/// An event seen in the current window, and how often it repeated since.
+struct Seen {
+    first: Instant,
+    repeats: usize,
+    event: Event,
+}
+
+/// Lets the first occurrence of an event through and suppresses identical
+/// ones for `window`. Once the window ends, repeats are reported as a single
+/// summary event ("seen 437 times in the last 60s") with the original
+/// fingerprint, so volume is controlled without losing signal entirely.
+pub struct Dedupe {
+    window: Duration,
+    seen: Mutex<HashMap<Vec<String>, Seen>>,
+    clock: SharedClock,
+}
+
+impl Dedupe {
+    pub fn new(window: Duration, clock: SharedClock) -> Dedupe {
+        Dedupe {
+            window: window,
+            seen: Mutex::new(HashMap::new()),
+            clock: clock,
+        }
+    }
+
+    /// Events are identical when their messages and fingerprints are, or
+    /// without a fingerprint, when logger, level, message and culprit are. The
+    /// default fingerprint of the level methods leaves the message out, which
+    /// groups different errors in Sentry but mustn't suppress them.
+    fn key(e: &Event) -> Vec<String> {
+        if !e.fingerprint.is_empty() {
+            let mut key = e.fingerprint.clone();
+            key.push(e.message.clone());
+            return key;
+        }
+        vec![e.logger.clone(), e.level.clone(), e.message.clone(), e.culprit.clone().unwrap_or_default()]
+    }
+
+    /// Whether `e` should be sent, counting it as a repeat otherwise.
+    pub fn admit(&self, e: &Event) -> bool {
+        let mut seen = match self.seen.lock() {
+            Ok(guard) => guard,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        let key = Dedupe::key(e);
+        let now = self.clock.instant();
+        if let Some(entry) = seen.get_mut(&key) {
+            if now.duration_since(entry.first) < self.window {
+                entry.repeats += 1;
+                return false;
+            }
+        }
+        // an expired entry still owed a summary is reported by `summaries` first
+        if seen.get(&key).map_or(true, |entry| entry.repeats == 0) {
+            // the copy summarizing repeats is no delivery of `e`, and mustn't
+            // keep it from settling as lost
+            let mut event = e.clone();
+            event.delivery = Tracker::default();
+            seen.insert(key,
+                        Seen {
+                            first: now,
+                            repeats: 0,
+                            event: event,
+                        });
+        }
+        true
+    }
+
+    /// Forgets events whose window ended, returning a summary for each that
+    /// repeated.
+    pub fn summaries(&self) -> Vec<Event> {
+        let mut seen = match self.seen.lock() {
+            Ok(guard) => guard,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        let now = self.clock.instant();
+        let expired: Vec<Vec<String>> = seen.iter()
+            .filter(|&(_, entry)| now.duration_since(entry.first) >= self.window)
+            .map(|(key, _)| key.clone())
+            .collect();
+        let mut summaries = vec![];
+        for key in expired {
+            if let Some(entry) = seen.remove(&key) {
+                if entry.repeats > 0 {
+                    summaries.push(self.summary(entry));
+                }
+            }
+        }
+        summaries
+    }
+
+    fn summary(&self, seen: Seen) -> Event {
+        let mut e = seen.event;
+        e.extra.insert("sentry.duplicate_of".to_string(), e.event_id.clone());
+        e.extra.insert("sentry.repeats".to_string(), seen.repeats.to_string());
+        e.event_id = uuid::Uuid::new_v4().simple().to_string();
+        e.timestamp = self.clock.now();
+        e.message = format!("{} (seen {} times in the last {}s)",
+                            e.message,
+                            seen.repeats,
+                            self.window.as_secs());
+        e
+    }
+}
@@ -0,0 +1,149 @@
+//! Tells the caller of `Sentry::log_event` what became of its event, for
+//! callers that need at-least-once semantics.
+
+use std::fmt;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+/// The final outcome of an event.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DeliveryStatus {
+    /// A destination accepted the event.
+    Sent,
+    /// Every destination was rate limiting the client, and the event was not
+    /// held for retry.
+    RateLimited,
+    /// The event was discarded: "disabled", "sampled", "ignored_logger",
+    /// "duplicate", "queue_full", "undelivered", "rejected" or
+    /// "unserializable", like the reasons of the `sentry_events_dropped_total`
+    /// metric, or "lost" when the client lost it before any outcome, e.g. to
+    /// a panic of the worker, a `reinit_after_fork` discarding the parent's
+    /// queue or the client dropped with the event still queued.
+    Dropped(&'static str),
+}
+
+#[derive(Default)]
+struct State {
+    status: Mutex<Option<DeliveryStatus>>,
+    settled: Condvar,
+}
+
+impl State {
+    fn lock(&self) -> MutexGuard<'_, Option<DeliveryStatus>> {
+        match self.status.lock() {
+            Ok(guard) => guard,
+            Err(poisoned) => poisoned.into_inner(),
+        }
+    }
+
+    fn settle(&self, status: DeliveryStatus) {
+        let mut current = self.lock();
+        if current.is_none() {
+            *current = Some(status);
+            self.settled.notify_all();
+        }
+    }
+}
+
+/// Shared by the copies of a tracked event; the last one dropped without an
+/// outcome, wherever the event was lost, settles it as "lost".
+struct Pending(Arc<State>);
+
+impl Drop for Pending {
+    fn drop(&mut self) {
+        self.0.settle(DeliveryStatus::Dropped("lost"));
+    }
+}
+
+/// Returned by `Sentry::log_event`; polled with `status` or blocked on with
+/// `wait`. Events held for retry settle once retried, so `wait` may time out
+/// during an outage:
+///
+/// ```
+/// # use std::time::Duration;
+/// # use sentry::{DeliveryStatus, Sentry};
+/// # let sentry = Sentry::disabled();
+/// let delivery = sentry.log_event(sentry.event("billing", "error", "Invoice run failed"));
+/// match delivery.wait(Duration::from_secs(5)) {
+///     Some(DeliveryStatus::Sent) => {}
+///     other => println!("event {} not sent: {:?}", delivery.event_id(), other),
+/// }
+/// ```
+#[derive(Clone)]
+pub struct EventDelivery {
+    event_id: String,
+    state: Arc<State>,
+}
+
+impl EventDelivery {
+    pub fn event_id(&self) -> &str {
+        &self.event_id
+    }
+
+    /// The outcome, `None` while the event is queued or held for retry.
+    pub fn status(&self) -> Option<DeliveryStatus> {
+        *self.state.lock()
+    }
+
+    /// Blocks up to `timeout` for the outcome.
+    pub fn wait(&self, timeout: Duration) -> Option<DeliveryStatus> {
+        let deadline = Instant::now() + timeout;
+        let mut status = self.state.lock();
+        while status.is_none() {
+            let now = Instant::now();
+            if now >= deadline {
+                break;
+            }
+            status = match self.state.settled.wait_timeout(status, deadline - now) {
+                Ok((guard, _)) => guard,
+                Err(poisoned) => poisoned.into_inner().0,
+            };
+        }
+        *status
+    }
+}
+
+impl fmt::Debug for EventDelivery {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("EventDelivery")
+            .field("event_id", &self.event_id)
+            .field("status", &self.status())
+            .finish()
+    }
+}
+
+/// Carried by an event through the worker to settle its `EventDelivery`.
+/// Events compare equal whatever their tracker.
+#[derive(Clone, Default)]
+pub struct Tracker(Option<Arc<Pending>>);
+
+impl Tracker {
+    /// Starts tracking the event `event_id`.
+    pub fn start(&mut self, event_id: &str) -> EventDelivery {
+        let state = Arc::new(State::default());
+        self.0 = Some(Arc::new(Pending(state.clone())));
+        EventDelivery {
+            event_id: event_id.to_string(),
+            state: state,
+        }
+    }
+
+    /// Settles the delivery, if tracked and not settled yet.
+    pub fn settle(&self, status: DeliveryStatus) {
+        if let Some(ref pending) = self.0 {
+            pending.0.settle(status);
+        }
+    }
+}
+
+impl PartialEq for Tracker {
+    fn eq(&self, _other: &Tracker) -> bool {
+        true
+    }
+}
+
+impl fmt::Debug for Tracker {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("Tracker")
+    }
+}
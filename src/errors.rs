@@ -5,8 +5,28 @@ error_chain! {
     links {
     }
 
+    errors {
+        Unserializable(reason: String) {
+            description("event can't be serialized")
+            display("Sentry event can't be serialized: {}", reason)
+        }
+        InvalidEvent(violations: String) {
+            description("event violates the Sentry schema")
+            display("Sentry event violates the schema: {}", violations)
+        }
+        InvalidCredential(reason: String) {
+            description("invalid Sentry credential")
+            display("Invalid Sentry credential: {}", reason)
+        }
+        InvalidAuthHeader(reason: String) {
+            description("invalid X-Sentry-Auth header")
+            display("Invalid X-Sentry-Auth header: {}", reason)
+        }
+    }
+
     foreign_links {
-        HyperError(::hyper::Error);
+        HyperError(::hyper::Error) #[cfg(feature = "hyper-transport")];
         Io(::std::io::Error);
+        Json(::serde_json::Error);
     }
 }
@@ -0,0 +1,131 @@
+//! A process-wide client, so libraries can report errors without a `Sentry`
+//! handle threaded through their APIs.
+
+use std::env;
+use std::error::Error;
+use std::fmt;
+use std::path::Path;
+use std::sync::{RwLock, RwLockReadGuard};
+
+use breadcrumbs::Breadcrumb;
+use internal;
+use scope;
+use session::CLOSE_TIMEOUT;
+use transaction::Transaction;
+use {DsnFromEnvError, MessageOptions, Sentry, SentryCredential, Settings};
+
+static CLIENT: RwLock<Option<Sentry>> = RwLock::new(None);
+
+fn client() -> RwLockReadGuard<'static, Option<Sentry>> {
+    match CLIENT.read() {
+        Ok(guard) => guard,
+        Err(poisoned) => poisoned.into_inner(),
+    }
+}
+
+/// Makes `sentry` the client of the free functions, returning the previous
+/// one. Until a client is set they report nothing:
+///
+/// ```
+/// # use sentry::{MessageOptions, Sentry};
+/// sentry::init(Sentry::disabled());
+/// sentry::capture_message("cache rebuilt", &MessageOptions::default());
+/// ```
+pub fn init(sentry: Sentry) -> Option<Sentry> {
+    let mut client = match CLIENT.write() {
+        Ok(guard) => guard,
+        Err(poisoned) => poisoned.into_inner(),
+    };
+    client.replace(sentry)
+}
+
+/// A handle to the global client, if one was set.
+pub fn current() -> Option<Sentry> {
+    client().clone()
+}
+
+/// `Sentry::capture_message` on the global client.
+pub fn capture_message(message: &str, options: &MessageOptions) {
+    if let Some(ref sentry) = *client() {
+        sentry.capture_message(message, options);
+    }
+}
+
+/// `Sentry::monitor_job` on the global client, or just `f` until one is set.
+pub fn monitor_job<F, T, E>(name: &str, f: F) -> Result<T, E>
+    where F: FnOnce() -> Result<T, E>,
+          E: fmt::Display
+{
+    // the job runs on a handle, not under the lock, so it may call `init`
+    match current() {
+        Some(sentry) => sentry.monitor_job(name, f),
+        None => f(),
+    }
+}
+
+/// `Sentry::capture_error` on the global client.
+pub fn capture_error<E: Error + ?Sized>(error: &E) {
+    if let Some(ref sentry) = *client() {
+        sentry.capture_error(error);
+    }
+}
+
+/// Adds `breadcrumb` to the scope of the current thread, recorded with the
+/// next event it captures, through the global client or any other.
+pub fn add_breadcrumb(breadcrumb: Breadcrumb) {
+    scope::configure_scope(|scope| scope.add_breadcrumb(breadcrumb));
+}
+
+// ends the transaction and closes the client when `run` returns or unwinds
+struct RunGuard {
+    sentry: Sentry,
+    transaction: Option<Transaction>,
+}
+
+impl Drop for RunGuard {
+    fn drop(&mut self) {
+        self.transaction.take();
+        if !self.sentry.close(CLOSE_TIMEOUT) {
+            warn!(target: internal::TARGET, "Sentry queue not drained after {:?}, exiting anyway", CLOSE_TIMEOUT);
+        }
+    }
+}
+
+/// Runs the `main` of a binary, `f`, with a client set up for it: created
+/// from `settings` and the `SENTRY_DSN` environment variable (disabled if it
+/// is unset or malformed), made the global client, reporting panics, and
+/// timing `f` as a transaction named after the binary, in the session of
+/// `Settings::session_tracking`. When `f` returns or panics, the session
+/// ends and queued events are flushed, for up to two seconds; exiting with
+/// `std::process::exit` skips that.
+///
+/// ```no_run
+/// # use sentry::Settings;
+/// # fn real_main() -> i32 { 0 }
+/// fn main() {
+///     let settings = Settings { release: "myapp@1.2.0".to_string(), ..Settings::default() };
+///     let code = sentry::run(settings, || real_main());
+///     std::process::exit(code);
+/// }
+/// ```
+pub fn run<T, F: FnOnce() -> T>(settings: Settings, f: F) -> T {
+    let sentry = match SentryCredential::from_env() {
+        Ok(credential) => Sentry::from_settings(settings, credential),
+        Err(DsnFromEnvError::Unset) => Sentry::with_credentials(settings, vec![]),
+        Err(e) => {
+            warn!(target: internal::TARGET, "Sentry disabled: {}", e);
+            Sentry::with_credentials(settings, vec![])
+        }
+    };
+    init(sentry.clone());
+    sentry.register_panic_handler::<fn(&::std::panic::PanicInfo)>(None);
+    let name = env::args()
+        .next()
+        .and_then(|program| Path::new(&program).file_stem().map(|stem| stem.to_string_lossy().into_owned()))
+        .unwrap_or_else(|| "main".to_string());
+    let _guard = RunGuard {
+        transaction: Some(sentry.start_transaction(&name, "main")),
+        sentry: sentry,
+    };
+    f()
+}
@@ -0,0 +1,210 @@
+//! Hardware sizing and operating system of the host, reported in the `device`
+//! and `os` contexts so that out-of-memory and performance-related errors can
+//! be put in perspective.
+
+use std::env;
+#[cfg(not(windows))]
+use std::fs;
+
+use num_cpus;
+use serde_json::Value;
+
+/// The `device` context: `arch`, `processor_count` and, on Linux and
+/// Windows, `memory_size` in bytes and the host `name`.
+pub fn context() -> Value {
+    let mut context = json!({
+        "type": "device",
+        "arch": env::consts::ARCH,
+        "processor_count": num_cpus::get(),
+    });
+    if let Some(memory_size) = memory_size() {
+        context["memory_size"] = json!(memory_size);
+    }
+    if let Some(hostname) = hostname() {
+        context["name"] = json!(hostname);
+    }
+    context
+}
+
+/// The `os` context: `name` and, where known, `version`, `build` and
+/// `kernel_version`.
+pub fn os_context() -> Value {
+    let mut context = json!({
+        "type": "os",
+        "name": os_name(),
+    });
+    for &(key, ref value) in &[("version", os_version()), ("build", os_build()), ("kernel_version", kernel_version())] {
+        if let Some(ref value) = *value {
+            context[key] = json!(value);
+        }
+    }
+    context
+}
+
+/// The value of `key` in an `os-release` file, ex: `VERSION_ID="22.04"`.
+pub fn os_release_value(os_release: &str, key: &str) -> Option<String> {
+    os_release.lines()
+        .filter_map(|line| {
+            let mut parts = line.splitn(2, '=');
+            match (parts.next(), parts.next()) {
+                (Some(name), Some(value)) if name.trim() == key => Some(value.trim().trim_matches('"').to_string()),
+                _ => None,
+            }
+        })
+        .next()
+}
+
+#[cfg(not(windows))]
+fn os_release(key: &str) -> Option<String> {
+    let os_release = fs::read_to_string("/etc/os-release")
+        .or_else(|_| fs::read_to_string("/usr/lib/os-release"))
+        .ok()?;
+    os_release_value(&os_release, key)
+}
+
+/// Total memory in bytes, from the `MemTotal` line of `/proc/meminfo`, ex:
+/// "MemTotal:       16318460 kB".
+#[cfg(not(windows))]
+fn memory_size() -> Option<u64> {
+    let meminfo = fs::read_to_string("/proc/meminfo").ok()?;
+    let line = meminfo.lines().find(|line| line.starts_with("MemTotal:"))?;
+    let kilobytes: u64 = line.split_whitespace().nth(1)?.parse().ok()?;
+    Some(kilobytes * 1024)
+}
+
+#[cfg(not(windows))]
+fn hostname() -> Option<String> {
+    let hostname = fs::read_to_string("/proc/sys/kernel/hostname").ok()?;
+    Some(hostname.trim().to_string()).filter(|hostname| !hostname.is_empty())
+}
+
+/// The distribution on Linux, ex: "Ubuntu", or the family of the OS.
+#[cfg(not(windows))]
+fn os_name() -> String {
+    os_release("NAME").unwrap_or_else(|| env::consts::OS.to_string())
+}
+
+#[cfg(not(windows))]
+fn os_version() -> Option<String> {
+    os_release("VERSION_ID")
+}
+
+#[cfg(not(windows))]
+fn os_build() -> Option<String> {
+    os_release("BUILD_ID")
+}
+
+#[cfg(not(windows))]
+fn kernel_version() -> Option<String> {
+    let release = fs::read_to_string("/proc/sys/kernel/osrelease").ok()?;
+    Some(release.trim().to_string()).filter(|release| !release.is_empty())
+}
+
+#[cfg(windows)]
+fn memory_size() -> Option<u64> {
+    windows::memory_size()
+}
+
+#[cfg(windows)]
+fn hostname() -> Option<String> {
+    windows::hostname()
+}
+
+#[cfg(windows)]
+fn os_name() -> String {
+    "Windows".to_string()
+}
+
+/// ex: "10.0", which Windows 11 still reports.
+#[cfg(windows)]
+fn os_version() -> Option<String> {
+    windows::version().map(|(major, minor, _)| format!("{}.{}", major, minor))
+}
+
+/// ex: "22631", which tells Windows 11 (22000 and above) from 10.
+#[cfg(windows)]
+fn os_build() -> Option<String> {
+    windows::version().map(|(_, _, build)| build.to_string())
+}
+
+#[cfg(windows)]
+fn kernel_version() -> Option<String> {
+    windows::version().map(|(major, minor, build)| format!("{}.{}.{}", major, minor, build))
+}
+
+#[cfg(windows)]
+mod windows {
+    use std::mem;
+    use std::ptr;
+
+    #[repr(C)]
+    struct OsVersionInfo {
+        size: u32,
+        major: u32,
+        minor: u32,
+        build: u32,
+        platform_id: u32,
+        service_pack: [u16; 128],
+    }
+
+    #[repr(C)]
+    struct MemoryStatus {
+        length: u32,
+        memory_load: u32,
+        total_phys: u64,
+        avail_phys: u64,
+        total_page_file: u64,
+        avail_page_file: u64,
+        total_virtual: u64,
+        avail_virtual: u64,
+        avail_extended_virtual: u64,
+    }
+
+    const COMPUTER_NAME_DNS_HOSTNAME: u32 = 1;
+
+    #[link(name = "ntdll")]
+    extern "system" {
+        fn RtlGetVersion(info: *mut OsVersionInfo) -> i32;
+    }
+
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn GlobalMemoryStatusEx(status: *mut MemoryStatus) -> i32;
+        fn GetComputerNameExW(name_type: u32, buffer: *mut u16, size: *mut u32) -> i32;
+    }
+
+    /// Major, minor and build number of the running Windows; unlike
+    /// `GetVersionEx`, not capped to the versions the executable's manifest
+    /// declares support for.
+    pub fn version() -> Option<(u32, u32, u32)> {
+        let mut info: OsVersionInfo = unsafe { mem::zeroed() };
+        info.size = mem::size_of::<OsVersionInfo>() as u32;
+        if unsafe { RtlGetVersion(&mut info) } != 0 {
+            return None;
+        }
+        Some((info.major, info.minor, info.build))
+    }
+
+    pub fn memory_size() -> Option<u64> {
+        let mut status: MemoryStatus = unsafe { mem::zeroed() };
+        status.length = mem::size_of::<MemoryStatus>() as u32;
+        if unsafe { GlobalMemoryStatusEx(&mut status) } == 0 {
+            return None;
+        }
+        Some(status.total_phys)
+    }
+
+    pub fn hostname() -> Option<String> {
+        // fails, setting the size needed including the terminating nul
+        let mut size = 0;
+        unsafe { GetComputerNameExW(COMPUTER_NAME_DNS_HOSTNAME, ptr::null_mut(), &mut size) };
+        if size == 0 {
+            return None;
+        }
+        let mut buffer = vec![0u16; size as usize];
+        if unsafe { GetComputerNameExW(COMPUTER_NAME_DNS_HOSTNAME, buffer.as_mut_ptr(), &mut size) } == 0 {
+            return None;
+        }
+        Some(String::from_utf16_lossy(&buffer[..size as usize]))
+    }
+}
@@ -0,0 +1,69 @@
+//! The client's own diagnostics, logged under the `sentry::internal` target so
+//! they can be turned on as a whole (`RUST_LOG=sentry::internal=debug`), and
+//! throttled so an outage of the Sentry server doesn't flood the application's
+//! logs with the same failure.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use log::LogLevel;
+
+/// The log target of every record the client emits about itself.
+pub const TARGET: &'static str = "sentry::internal";
+
+/// A repeated message is logged at most once per window.
+pub const WINDOW: Duration = Duration::from_secs(60);
+
+/// Distinct messages remembered; beyond it everything is forgotten, so a
+/// stream of unique messages can't grow the map.
+const MAX_MESSAGES: usize = 100;
+
+/// Suppresses repeats of a message within `window` of its last logging.
+pub struct Throttle {
+    window: Duration,
+    seen: Mutex<HashMap<String, (Instant, usize)>>, // last logged, repeats suppressed since
+}
+
+impl Throttle {
+    pub fn new(window: Duration) -> Throttle {
+        Throttle {
+            window: window,
+            seen: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// `None` if `message` was logged within the window, otherwise how many
+    /// repeats were suppressed since it last was.
+    pub fn admit(&self, message: &str) -> Option<usize> {
+        let mut seen = match self.seen.lock() {
+            Ok(guard) => guard,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        let now = Instant::now();
+        if let Some(&mut (ref mut logged, ref mut suppressed)) = seen.get_mut(message) {
+            if now.duration_since(*logged) < self.window {
+                *suppressed += 1;
+                return None;
+            }
+            let repeats = *suppressed;
+            *logged = now;
+            *suppressed = 0;
+            return Some(repeats);
+        }
+        if seen.len() >= MAX_MESSAGES {
+            seen.clear();
+        }
+        seen.insert(message.to_string(), (now, 0));
+        Some(0)
+    }
+}
+
+/// Logs `message` at `level` under `TARGET`, unless `throttle` suppresses it.
+pub fn log(throttle: &Throttle, level: LogLevel, message: &str) {
+    match throttle.admit(message) {
+        Some(0) => log!(target: TARGET, level, "{}", message),
+        Some(repeats) => log!(target: TARGET, level, "{} (repeated {} more times)", message, repeats),
+        None => {}
+    }
+}
@@ -0,0 +1,62 @@
+//! Cron monitoring: the check-ins of a job run by `Sentry::monitor_job`, one
+//! when it starts and one when it ends, so Sentry can alert on jobs that fail,
+//! hang or don't run at all.
+
+use uuid::Uuid;
+
+// see https://develop.sentry.dev/sdk/telemetry/check-ins/
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CheckInStatus {
+    InProgress,
+    Ok,
+    Error,
+}
+
+/// A check-in of the monitor `monitor_slug`, sharing its `check_in_id` with
+/// the other check-ins of the same run.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CheckIn {
+    pub check_in_id: String,
+    pub monitor_slug: String,
+    pub status: CheckInStatus,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub duration: Option<f64>, // seconds
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub release: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub environment: String,
+}
+
+impl CheckIn {
+    /// The check-in of a job starting to run.
+    pub fn start(job: &str, release: &str, environment: &str) -> CheckIn {
+        CheckIn {
+            check_in_id: Uuid::new_v4().simple().to_string(),
+            monitor_slug: slug(job),
+            status: CheckInStatus::InProgress,
+            duration: None,
+            release: release.to_string(),
+            environment: environment.to_string(),
+        }
+    }
+
+    /// The check-in of the same run ending with `status` after `duration`
+    /// seconds.
+    pub fn finish(&self, status: CheckInStatus, duration: f64) -> CheckIn {
+        CheckIn {
+            status: status,
+            duration: Some(duration),
+            ..self.clone()
+        }
+    }
+}
+
+/// Monitor slugs only take lowercase letters, digits, `-` and `_`, ex:
+/// "nightly.export" is monitored as "nightly-export".
+fn slug(job: &str) -> String {
+    job.chars()
+        .map(|c| c.to_ascii_lowercase())
+        .map(|c| if c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_' || c == '-' { c } else { '-' })
+        .collect()
+}
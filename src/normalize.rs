@@ -0,0 +1,101 @@
+//! Bounds the shape of user data in `contexts` and `extra` before sending, the
+//! way Relay normalizes it, so a deeply nested or huge value can't turn an
+//! event into megabytes Sentry would reject anyway.
+
+use std::fmt;
+
+use serde::Serialize;
+use serde_json::{self, Map, Value};
+
+use trim;
+use Event;
+
+/// Levels of arrays and objects kept below a context; deeper ones are sent as
+/// their JSON text.
+pub const MAX_DEPTH: usize = 5;
+
+/// Items kept of an array or object in a context, and entries of `extra`.
+pub const MAX_ITEMS: usize = 100;
+
+/// Length in bytes of strings in a context, stringified values included.
+pub const MAX_STRING_LENGTH: usize = 512;
+
+/// The JSON of `value`, or its `Debug` formatting if it can't be serialized,
+/// ex: a map with non-string keys.
+pub fn to_value<T: Serialize + fmt::Debug + ?Sized>(value: &T) -> Value {
+    serde_json::to_value(value).unwrap_or_else(|_| Value::String(format!("{:?}", value)))
+}
+
+/// Normalizes the contexts and extra of `e`, noting what was cut in `_meta`.
+pub fn event(e: &mut Event) {
+    for (key, context) in e.contexts.iter_mut() {
+        if let Some(meta) = normalize(context, 0) {
+            e.meta["contexts"][key] = meta;
+        }
+    }
+    let len = e.extra.len();
+    if len > MAX_ITEMS {
+        // the same entries whatever the order of the map
+        let mut keys: Vec<String> = e.extra.keys().cloned().collect();
+        keys.sort();
+        for key in &keys[MAX_ITEMS..] {
+            e.extra.remove(key);
+        }
+        e.meta["extra"] = trim::removed(len);
+    }
+}
+
+/// Bounds `value`, nested `depth` levels below its context, returning the
+/// `_meta` of what was cut, if anything.
+fn normalize(value: &mut Value, depth: usize) -> Option<Value> {
+    if depth >= MAX_DEPTH && (value.is_array() || value.is_object()) {
+        let mut text = value.to_string();
+        trim::truncate(&mut text, MAX_STRING_LENGTH);
+        *value = Value::String(text);
+        return None;
+    }
+    let mut meta = Map::new();
+    let len = match *value {
+        Value::String(ref mut s) => {
+            let len = s.len();
+            if trim::truncate(s, MAX_STRING_LENGTH) {
+                return Some(trim::truncated(len, s));
+            }
+            return None;
+        }
+        Value::Array(ref mut items) => {
+            let len = items.len();
+            items.truncate(MAX_ITEMS);
+            for (i, item) in items.iter_mut().enumerate() {
+                if let Some(item_meta) = normalize(item, depth + 1) {
+                    meta.insert(i.to_string(), item_meta);
+                }
+            }
+            len
+        }
+        Value::Object(ref mut map) => {
+            let len = map.len();
+            let dropped: Vec<String> = map.keys().skip(MAX_ITEMS).cloned().collect();
+            for key in dropped {
+                map.remove(&key);
+            }
+            for (key, item) in map.iter_mut() {
+                if let Some(item_meta) = normalize(item, depth + 1) {
+                    meta.insert(key.clone(), item_meta);
+                }
+            }
+            len
+        }
+        _ => return None,
+    };
+    if len > MAX_ITEMS {
+        if let Value::Object(removed) = trim::removed(len) {
+            meta.extend(removed);
+        }
+    }
+    if meta.is_empty() {
+        None
+    } else {
+        Some(Value::Object(meta))
+    }
+}
@@ -0,0 +1,173 @@
+//! A bridge turning the spans of an OpenTelemetry tracer into Sentry
+//! transactions, so applications instrumented with OpenTelemetry get Sentry
+//! performance data without instrumenting twice.
+
+use std::collections::HashMap;
+use std::sync::{Mutex, MutexGuard};
+use std::time::Duration;
+
+use opentelemetry::Context;
+use opentelemetry::trace::{Span as _, SpanId, SpanKind, Status, TraceContextExt};
+use opentelemetry_sdk::error::OTelSdkResult;
+use opentelemetry_sdk::trace::{Span as SdkSpan, SpanData, SpanProcessor};
+use serde_json::Value;
+
+use timestamp::Timestamp;
+use {Sentry, Span, TraceContext};
+
+/// An OpenTelemetry span processor sending each local root span, one without
+/// a parent or whose parent is remote, as a Sentry transaction, with the spans
+/// started under it as its spans:
+///
+/// ```ignore
+/// let provider = SdkTracerProvider::builder()
+///     .with_span_processor(SentrySpanProcessor::new(sentry.clone()))
+///     .build();
+/// ```
+///
+/// Spans ending after their root are dropped, since the transaction was sent
+/// already. The `sentry.op` attribute sets the operation of a span, guessed
+/// from its kind and attributes otherwise; other attributes become its data.
+pub struct SentrySpanProcessor {
+    sentry: Sentry,
+    state: Mutex<State>,
+}
+
+#[derive(Default)]
+struct State {
+    roots: HashMap<SpanId, SpanId>, // open span => its local root
+    children: HashMap<SpanId, Vec<Span>>, // local root => ended spans under it
+}
+
+impl SentrySpanProcessor {
+    pub fn new(sentry: Sentry) -> SentrySpanProcessor {
+        sentry.add_integration("opentelemetry");
+        SentrySpanProcessor {
+            sentry: sentry,
+            state: Mutex::new(State::default()),
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, State> {
+        match self.state.lock() {
+            Ok(guard) => guard,
+            Err(poisoned) => poisoned.into_inner(),
+        }
+    }
+}
+
+impl ::std::fmt::Debug for SentrySpanProcessor {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+        f.write_str("SentrySpanProcessor")
+    }
+}
+
+/// ex: "http.server", from the `sentry.op` attribute or the span's kind and
+/// attributes.
+fn op(data: &SpanData) -> Option<String> {
+    let attribute = |key: &str| data.attributes.iter().find(|kv| kv.key.as_str() == key);
+    if let Some(op) = attribute("sentry.op") {
+        return Some(op.value.as_str().into_owned());
+    }
+    let http = attribute("http.request.method").is_some() || attribute("http.method").is_some();
+    match data.span_kind {
+        SpanKind::Server if http => Some("http.server".to_string()),
+        SpanKind::Client if http => Some("http.client".to_string()),
+        _ if attribute("db.system").is_some() => Some("db".to_string()),
+        _ if attribute("messaging.system").is_some() => Some("queue".to_string()),
+        _ => None,
+    }
+}
+
+fn status(status: &Status) -> &'static str {
+    match *status {
+        Status::Error { .. } => "internal_error",
+        Status::Ok | Status::Unset => "ok",
+    }
+}
+
+fn context(data: &SpanData) -> TraceContext {
+    TraceContext {
+        trace_id: data.span_context.trace_id().to_string(),
+        span_id: data.span_context.span_id().to_string(),
+        parent_span_id: if data.parent_span_id == SpanId::INVALID {
+            None
+        } else {
+            Some(data.parent_span_id.to_string())
+        },
+        op: op(data),
+    }
+}
+
+fn span(data: &SpanData) -> Span {
+    let mut span = Span::new(&context(data),
+                             Timestamp::from(data.start_time),
+                             Timestamp::from(data.end_time));
+    span.description = Some(data.name.to_string());
+    span.status = Some(status(&data.status).to_string());
+    for kv in &data.attributes {
+        if kv.key.as_str() != "sentry.op" {
+            span.data.insert(kv.key.as_str().to_string(), Value::String(kv.value.as_str().into_owned()));
+        }
+    }
+    span
+}
+
+impl SpanProcessor for SentrySpanProcessor {
+    fn on_start(&self, span: &mut SdkSpan, cx: &Context) {
+        if !self.sentry.is_enabled() {
+            return;
+        }
+        let span_id = span.span_context().span_id();
+        let parent = cx.span().span_context().clone();
+        let mut state = self.lock();
+        let root = if parent.is_valid() && !parent.is_remote() {
+            state.roots.get(&parent.span_id()).cloned().unwrap_or(span_id)
+        } else {
+            span_id
+        };
+        state.roots.insert(span_id, root);
+    }
+
+    fn on_end(&self, data: SpanData) {
+        let span_id = data.span_context.span_id();
+        let spans = {
+            let mut state = self.lock();
+            match state.roots.get(&span_id).cloned() {
+                Some(root) if root != span_id => {
+                    state.roots.remove(&span_id);
+                    state.children.entry(root).or_insert_with(Vec::new).push(span(&data));
+                    return;
+                }
+                Some(_) => {
+                    state.roots.retain(|_, root| *root != span_id);
+                    state.children.remove(&span_id).unwrap_or_default()
+                }
+                // started before the processor was installed, or while disabled
+                None => return,
+            }
+        };
+        let mut e = self.sentry.transaction(&data.name,
+                                            &context(&data),
+                                            Some(status(&data.status)),
+                                            Timestamp::from(data.start_time),
+                                            Timestamp::from(data.end_time),
+                                            spans);
+        for kv in &data.attributes {
+            if kv.key.as_str() != "sentry.op" {
+                e.set_extra(kv.key.as_str().to_string(), kv.value.as_str().into_owned());
+            }
+        }
+        self.sentry.log_event(e);
+    }
+
+    fn force_flush(&self) -> OTelSdkResult {
+        self.sentry.flush(Duration::from_secs(5));
+        Ok(())
+    }
+
+    fn shutdown_with_timeout(&self, timeout: Duration) -> OTelSdkResult {
+        self.sentry.flush(timeout);
+        Ok(())
+    }
+}
@@ -0,0 +1,54 @@
+//! Messages of panic payloads, the values given to `panic!` or
+//! `std::panic::panic_any`.
+
+use std::any::Any;
+use std::fmt::{Debug, Display};
+use std::sync::RwLock;
+
+/// Reads a message out of a payload of a type it knows.
+pub type Extractor = Box<Fn(&(Any + Send)) -> Option<String> + Send + Sync>;
+
+/// An extractor of payloads of type `T`, formatted with `Display`:
+/// `sentry.add_panic_payload_extractor(sentry::display_payload::<MyError>)`.
+pub fn display_payload<T: Display + 'static>(payload: &(Any + Send)) -> Option<String> {
+    payload.downcast_ref::<T>().map(|payload| payload.to_string())
+}
+
+/// An extractor of payloads of type `T`, formatted with `Debug`.
+pub fn debug_payload<T: Debug + 'static>(payload: &(Any + Send)) -> Option<String> {
+    payload.downcast_ref::<T>().map(|payload| format!("{:?}", payload))
+}
+
+/// The extractors registered on a client, tried in order after the string
+/// payloads of `panic!`.
+#[derive(Default)]
+pub struct Extractors {
+    extractors: RwLock<Vec<Extractor>>,
+}
+
+impl Extractors {
+    pub fn add(&self, extractor: Extractor) {
+        match self.extractors.write() {
+            Ok(mut guard) => guard.push(extractor),
+            Err(poisoned) => poisoned.into_inner().push(extractor),
+        }
+    }
+
+    /// The message of `payload`, "Box<Any>" if no extractor knows its type.
+    pub fn message(&self, payload: &(Any + Send)) -> String {
+        if let Some(s) = payload.downcast_ref::<&'static str>() {
+            return s.to_string();
+        }
+        if let Some(s) = payload.downcast_ref::<String>() {
+            return s.clone();
+        }
+        let extractors = match self.extractors.read() {
+            Ok(guard) => guard,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        extractors.iter()
+            .filter_map(|extract| extract(payload))
+            .next()
+            .unwrap_or_else(|| "Box<Any>".to_string())
+    }
+}
@@ -0,0 +1,213 @@
+//! The HTTP request an event happened during, and the scrubbing of its
+//! sensitive parts.
+
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::str;
+
+use serde_json::{self, Value};
+
+/// Replaces scrubbed values.
+pub const FILTERED: &'static str = "[Filtered]";
+
+/// Cookies, query parameters and headers whose name contains one of these,
+/// case-insensitively, are scrubbed unless allowed.
+pub const DEFAULT_SCRUBBED_NAMES: [&'static str; 10] = ["session",
+                                                         "sid",
+                                                         "token",
+                                                         "auth",
+                                                         "password",
+                                                         "passwd",
+                                                         "secret",
+                                                         "api_key",
+                                                         "apikey",
+                                                         "csrf"];
+
+// see https://develop.sentry.dev/sdk/event-payloads/request/
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub struct Request {
+    /// Without the query string.
+    pub url: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub method: Option<String>,
+    /// ex: "page=2&token=abc"
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub query_string: Option<String>,
+    /// The Cookie header, ex: "theme=dark; sessionid=abc"
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cookies: Option<String>,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub headers: HashMap<String, String>,
+    /// CGI-style variables, ex: "REMOTE_ADDR"
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub env: HashMap<String, String>,
+}
+
+impl Request {
+    /// A request to `url`, its query string split off into `query_string`.
+    pub fn new(method: &str, url: &str) -> Request {
+        let (url, query_string) = match url.find('?') {
+            Some(i) => (&url[..i], Some(url[i + 1..].to_string())),
+            None => (url, None),
+        };
+        Request {
+            url: url.to_string(),
+            method: Some(method.to_string()),
+            query_string: query_string,
+            ..Request::default()
+        }
+    }
+}
+
+/// Zeroes the host part of `ip`: the last octet of an IPv4 address, the last
+/// 80 bits of an IPv6 one. `None` if it isn't an IP address.
+pub fn anonymize_ip(ip: &str) -> Option<String> {
+    match ip.trim().parse() {
+        Ok(IpAddr::V4(ip)) => {
+            let octets = ip.octets();
+            Some(format!("{}.{}.{}.0", octets[0], octets[1], octets[2]))
+        }
+        Ok(IpAddr::V6(ip)) => {
+            let mut segments = ip.segments();
+            for segment in &mut segments[3..] {
+                *segment = 0;
+            }
+            Some(IpAddr::from(segments).to_string())
+        }
+        Err(_) => None,
+    }
+}
+
+/// Headers carrying client addresses, comma-separated.
+const IP_HEADERS: [&'static str; 2] = ["x-forwarded-for", "x-real-ip"];
+
+impl Request {
+    /// Anonymizes the client addresses in `REMOTE_ADDR` and the forwarding
+    /// headers, dropping values that can't be.
+    pub fn anonymize_ips(&mut self) {
+        if let Some(addr) = self.env.remove("REMOTE_ADDR") {
+            if let Some(addr) = anonymize_ip(&addr) {
+                self.env.insert("REMOTE_ADDR".to_string(), addr);
+            }
+        }
+        for (name, value) in &mut self.headers {
+            if IP_HEADERS.iter().any(|header| name.eq_ignore_ascii_case(header)) {
+                *value = value.split(',').filter_map(anonymize_ip).collect::<Vec<_>>().join(", ");
+            }
+        }
+    }
+}
+
+/// Scrubs the values of sensitive cookies, query parameters, headers and
+/// CGI-style variables.
+#[derive(Debug, Clone)]
+pub struct Scrubber {
+    denied: Vec<String>,
+    allowed: Vec<String>,
+}
+
+impl Scrubber {
+    /// Scrubs names containing one of `DEFAULT_SCRUBBED_NAMES` or `denied`,
+    /// except those equal to one of `allowed`, all case-insensitively.
+    pub fn new(denied: &[String], allowed: &[String]) -> Scrubber {
+        Scrubber {
+            denied: DEFAULT_SCRUBBED_NAMES.iter()
+                .map(|name| name.to_string())
+                .chain(denied.iter().map(|name| name.to_lowercase()))
+                .collect(),
+            allowed: allowed.iter().map(|name| name.to_lowercase()).collect(),
+        }
+    }
+
+    fn is_denied(&self, name: &str) -> bool {
+        let name = name.trim().to_lowercase();
+        !self.allowed.contains(&name) && self.denied.iter().any(|denied| name.contains(&denied[..]))
+    }
+
+    pub fn scrub(&self, request: &mut Request) {
+        if let Some(query_string) = request.query_string.take() {
+            request.query_string = Some(self.scrub_pairs(&query_string, "&"));
+        }
+        if let Some(cookies) = request.cookies.take() {
+            request.cookies = Some(self.scrub_pairs(&cookies, "; "));
+        }
+        for (name, value) in &mut request.headers {
+            if name.eq_ignore_ascii_case("cookie") {
+                *value = self.scrub_pairs(value, "; ");
+            } else if name.eq_ignore_ascii_case("set-cookie") || self.is_denied(name) {
+                *value = FILTERED.to_string();
+            }
+        }
+        // CGI-style variables carry the same data, ex: "HTTP_COOKIE"
+        for (name, value) in &mut request.env {
+            if name.eq_ignore_ascii_case("QUERY_STRING") {
+                *value = self.scrub_pairs(value, "&");
+            } else if name.eq_ignore_ascii_case("HTTP_COOKIE") {
+                *value = self.scrub_pairs(value, "; ");
+            } else if self.is_denied(name) {
+                *value = FILTERED.to_string();
+            }
+        }
+    }
+
+    /// Scrubs the query parameters of `url`.
+    pub fn scrub_url(&self, url: &str) -> String {
+        let mut parts = url.splitn(2, '?');
+        match (parts.next(), parts.next()) {
+            (Some(path), Some(query)) => format!("{}?{}", path, self.scrub_pairs(query, "&")),
+            _ => url.to_string(),
+        }
+    }
+
+    /// Scrubs an HTTP body: the values of scrubbed names in a JSON document,
+    /// at any depth, or in a form. Other text is kept as is, and binary
+    /// content replaced by its size.
+    pub fn scrub_body(&self, body: &[u8]) -> String {
+        let text = match str::from_utf8(body) {
+            Ok(text) => text,
+            Err(_) => return format!("[{} bytes]", body.len()),
+        };
+        match serde_json::from_str::<Value>(text) {
+            Ok(mut json) if json.is_object() || json.is_array() => {
+                self.scrub_json(&mut json);
+                json.to_string()
+            }
+            _ if text.contains('=') && !text.contains(char::is_whitespace) => self.scrub_pairs(text, "&"),
+            _ => text.to_string(),
+        }
+    }
+
+    fn scrub_json(&self, value: &mut Value) {
+        match *value {
+            Value::Object(ref mut map) => {
+                for (name, value) in map.iter_mut() {
+                    if self.is_denied(name) {
+                        *value = Value::String(FILTERED.to_string());
+                    } else {
+                        self.scrub_json(value);
+                    }
+                }
+            }
+            Value::Array(ref mut items) => {
+                for item in items {
+                    self.scrub_json(item);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Scrubs the values of `name=value` pairs joined by `separator`.
+    fn scrub_pairs(&self, pairs: &str, separator: &str) -> String {
+        pairs.split(separator.trim())
+            .map(|pair| {
+                let mut parts = pair.splitn(2, '=');
+                match (parts.next(), parts.next()) {
+                    (Some(name), Some(_)) if self.is_denied(name) => format!("{}={}", name.trim(), FILTERED),
+                    _ => pair.trim().to_string(),
+                }
+            })
+            .collect::<Vec<_>>()
+            .join(separator)
+    }
+}
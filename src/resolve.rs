@@ -0,0 +1,88 @@
+//! Resolution of the Sentry host, pluggable and optionally cached.
+
+use std::collections::HashMap;
+use std::io;
+use std::net::{SocketAddr, ToSocketAddrs};
+use std::sync::{Mutex, RwLock};
+use std::time::{Duration, Instant};
+
+use errors::*;
+
+/// Resolves a host and port to the addresses to connect to, e.g. from a
+/// static table or a resolver other than the system's.
+pub trait Resolve: Send + Sync {
+    fn resolve(&self, host: &str, port: u16) -> io::Result<Vec<SocketAddr>>;
+}
+
+impl<F> Resolve for F
+    where F: Fn(&str, u16) -> io::Result<Vec<SocketAddr>> + Send + Sync
+{
+    fn resolve(&self, host: &str, port: u16) -> io::Result<Vec<SocketAddr>> {
+        self(host, port)
+    }
+}
+
+type Cache = HashMap<(String, u16), (Instant, Vec<SocketAddr>)>;
+
+/// Resolves through the system unless a custom resolver is set, caching the
+/// answers for `ttl` if given, so frequent senders don't hit DNS per event
+/// and keep working through short DNS outages.
+pub struct Resolver {
+    custom: RwLock<Option<Box<Resolve>>>,
+    ttl: Option<Duration>,
+    cache: Mutex<Cache>,
+}
+
+impl Resolver {
+    pub fn new(ttl: Option<Duration>) -> Resolver {
+        Resolver {
+            custom: RwLock::new(None),
+            ttl: ttl,
+            cache: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn set_custom(&self, resolver: Box<Resolve>) {
+        match self.custom.write() {
+            Ok(mut guard) => *guard = Some(resolver),
+            Err(poisoned) => *poisoned.into_inner() = Some(resolver),
+        }
+        self.lock_cache().clear();
+    }
+
+    pub fn resolve(&self, host: &str, port: u16) -> Result<Vec<SocketAddr>> {
+        let key = (host.to_string(), port);
+        if let Some(ttl) = self.ttl {
+            if let Some(&(resolved_at, ref addrs)) = self.lock_cache().get(&key) {
+                if resolved_at.elapsed() < ttl {
+                    return Ok(addrs.clone());
+                }
+            }
+        }
+
+        let addrs = {
+            let custom = match self.custom.read() {
+                Ok(guard) => guard,
+                Err(poisoned) => poisoned.into_inner(),
+            };
+            match *custom {
+                Some(ref resolver) => resolver.resolve(host, port)?,
+                None => (host, port).to_socket_addrs()?.collect(),
+            }
+        };
+        if addrs.is_empty() {
+            bail!("{} resolved to no address", host);
+        }
+        if self.ttl.is_some() {
+            self.lock_cache().insert(key, (Instant::now(), addrs.clone()));
+        }
+        Ok(addrs)
+    }
+
+    fn lock_cache<'a>(&'a self) -> ::std::sync::MutexGuard<'a, Cache> {
+        match self.cache.lock() {
+            Ok(guard) => guard,
+            Err(poisoned) => poisoned.into_inner(),
+        }
+    }
+}
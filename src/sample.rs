@@ -0,0 +1,39 @@
+//! Sampling events by logger, so one noisy subsystem can be down-sampled
+//! without losing events elsewhere.
+
+/// The logger named by a pattern, `"payments"` or `"payments.*"`.
+pub fn prefix(pattern: &str) -> &str {
+    if pattern.ends_with(".*") {
+        &pattern[..pattern.len() - 2]
+    } else {
+        pattern
+    }
+}
+
+/// Whether `logger` is the one named `prefix` or one of its descendants,
+/// below a dot or, for module paths, `::`.
+pub fn is_under(logger: &str, prefix: &str) -> bool {
+    logger.starts_with(prefix) &&
+    (logger.len() == prefix.len() || logger[prefix.len()..].starts_with('.') ||
+     logger[prefix.len()..].starts_with("::"))
+}
+
+/// The rate of the longest pattern of `rates` matching `logger`, `default`
+/// if none does.
+pub fn rate_for(logger: &str, default: Option<f32>, rates: &[(String, f32)]) -> Option<f32> {
+    rates.iter()
+        .map(|&(ref pattern, rate)| (prefix(pattern), rate))
+        .filter(|&(prefix, _)| is_under(logger, prefix))
+        .max_by_key(|&(prefix, _)| prefix.len())
+        .map(|(_, rate)| rate)
+        .or(default)
+}
+
+/// Whether to keep an event at `rate`, decided from its random id rather
+/// than a generator of our own.
+pub fn keep(event_id: &str, rate: f32) -> bool {
+    match event_id.get(..8).and_then(|bits| u32::from_str_radix(bits, 16).ok()) {
+        Some(bits) => (bits as f64) < rate as f64 * (u32::max_value() as f64 + 1.0),
+        None => true,
+    }
+}
@@ -0,0 +1,248 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+use serde::Serialize;
+use serde_json::Value;
+use std::fmt;
+
+use internal;
+use normalize;
+use Event;
+use User;
+use request::Request;
+use trace::TraceContext;
+use breadcrumbs::{Breadcrumb, Breadcrumbs};
+
+/// Contextual data layered onto events when they are captured.
+///
+/// Values already set on an event win over scope data, which in turn wins
+/// over the client's `Settings`; see `Event::merge_from`. The level is the
+/// exception, a scope level overrides the event's.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Scope {
+    pub server_name: Option<String>,
+    pub release: Option<String>,
+    pub environment: Option<String>,
+    pub tags: HashMap<String, String>,
+    pub extra: HashMap<String, String>,
+    pub fingerprint: Option<Vec<String>>,
+    pub user: Option<User>,
+    pub level: Option<String>,
+    pub transaction: Option<String>,
+    pub contexts: HashMap<String, Value>,
+    pub breadcrumbs: Breadcrumbs,
+    pub request: Option<Request>,
+    /// Evaluated feature flags, oldest first, see `add_feature_flag`.
+    pub feature_flags: Vec<(String, bool)>,
+    pub trace: Option<TraceContext>,
+}
+
+/// Feature flags a scope keeps, the latest evaluated ones.
+pub const MAX_FEATURE_FLAGS: usize = 100;
+
+impl Scope {
+    pub fn set_tag(&mut self, key: String, value: String) {
+        self.tags.insert(key, value);
+    }
+
+    pub fn remove_tag(&mut self, key: &str) {
+        self.tags.remove(key);
+    }
+
+    pub fn set_extra(&mut self, key: String, value: String) {
+        self.extra.insert(key, value);
+    }
+
+    pub fn remove_extra(&mut self, key: &str) {
+        self.extra.remove(key);
+    }
+
+    /// Sets or, with `None`, clears the user affected by events.
+    pub fn set_user(&mut self, user: Option<User>) {
+        self.user = user;
+    }
+
+    /// Overrides the level of events, or stops overriding it with `None`.
+    pub fn set_level(&mut self, level: Option<&str>) {
+        self.level = level.map(|l| l.to_owned());
+    }
+
+    /// Names the unit of work events happen in, e.g. a route or job name.
+    pub fn set_transaction(&mut self, transaction: Option<&str>) {
+        self.transaction = transaction.map(|t| t.to_owned());
+    }
+
+    /// Sets the context named `key`, either one Sentry knows (`"runtime"`,
+    /// `"app"`...) or a custom one such as `"game_state"` or `"tenant"`, as an
+    /// object: `json!({"level": 3, "lives": 2})`.
+    pub fn set_context(&mut self, key: String, context: Value) {
+        self.contexts.insert(key, context);
+    }
+
+    /// Sets the context named `key` to any serializable value, ex: a struct
+    /// deriving `Serialize`, or to its `Debug` formatting if serializing it
+    /// fails.
+    pub fn set_context_value<T: Serialize + fmt::Debug + ?Sized>(&mut self, key: String, context: &T) {
+        self.contexts.insert(key, normalize::to_value(context));
+    }
+
+    pub fn remove_context(&mut self, key: &str) {
+        self.contexts.remove(key);
+    }
+
+    /// Records the result of evaluating the feature flag `name`, sent in the
+    /// `flags` context so errors can be correlated with rollouts. A flag
+    /// evaluated again moves to the end; beyond `MAX_FEATURE_FLAGS` the least
+    /// recently evaluated ones are dropped.
+    pub fn add_feature_flag(&mut self, name: &str, result: bool) {
+        self.feature_flags.retain(|&(ref flag, _)| flag != name);
+        if self.feature_flags.len() == MAX_FEATURE_FLAGS {
+            self.feature_flags.remove(0);
+        }
+        self.feature_flags.push((name.to_string(), result));
+    }
+
+    /// Sets or, with `None`, clears the span active on this scope, embedded as
+    /// the `trace` context of events captured meanwhile.
+    pub fn set_trace(&mut self, trace: Option<TraceContext>) {
+        self.trace = trace;
+    }
+
+    /// Sets or, with `None`, clears the HTTP request being handled, e.g. from
+    /// a server middleware. Its sensitive parts are scrubbed when sent.
+    pub fn set_request(&mut self, request: Option<Request>) {
+        self.request = request;
+    }
+
+    /// Records `breadcrumb`, evicting the oldest one once `max_breadcrumbs`
+    /// are kept.
+    pub fn add_breadcrumb(&mut self, breadcrumb: Breadcrumb) {
+        self.breadcrumbs.push(breadcrumb);
+    }
+
+    /// Keeps at most `max` breadcrumbs, 100 by default.
+    pub fn set_max_breadcrumbs(&mut self, max: usize) {
+        self.breadcrumbs.set_capacity(max);
+    }
+
+    pub fn clear_breadcrumbs(&mut self) {
+        self.breadcrumbs.clear();
+    }
+
+    /// An owned copy of the scope of the current thread, to capture events
+    /// with later, e.g. from a job queued for another thread.
+    pub fn snapshot() -> ScopeSnapshot {
+        let mut snapshot = ScopeSnapshot { scope: Scope::default() };
+        try_with_current(|scope| snapshot.scope = scope.clone());
+        snapshot
+    }
+}
+
+/// A scope taken with `Scope::snapshot`, which can be sent to other threads:
+///
+/// ```
+/// # use sentry::Scope;
+/// sentry::configure_scope(|scope| scope.set_tag("job".to_string(), "invoice-run".to_string()));
+/// let snapshot = Scope::snapshot();
+/// std::thread::spawn(move || {
+///     snapshot.bind(|| {
+///         // events captured here carry the "job" tag
+///     })
+/// }).join().unwrap();
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScopeSnapshot {
+    scope: Scope,
+}
+
+impl ScopeSnapshot {
+    pub fn scope(&self) -> &Scope {
+        &self.scope
+    }
+
+    /// Fills in whatever `e` leaves unset from the snapshot, before it's sent
+    /// with `Sentry::log_event`, which only fills in the rest from the scope
+    /// of the calling thread.
+    pub fn apply(&self, e: &mut Event) {
+        e.merge_from(&self.scope);
+    }
+
+    /// Runs `f` with the snapshot as the scope of the current thread, whose
+    /// own scope is restored afterwards, even if `f` panics. Within
+    /// `configure_scope` the scope can't be replaced, and `f` runs with the
+    /// thread's own.
+    pub fn bind<R, F: FnOnce() -> R>(&self, f: F) -> R {
+        let previous = CURRENT.with(|scope| match scope.try_borrow_mut() {
+            Ok(mut scope) => Some(::std::mem::replace(&mut *scope, self.scope.clone())),
+            Err(_) => {
+                in_use("bind a scope snapshot");
+                None
+            }
+        });
+        let _restore = Restore(previous);
+        f()
+    }
+}
+
+/// Puts back the scope a `ScopeSnapshot::bind` replaced.
+struct Restore(Option<Scope>);
+
+impl Drop for Restore {
+    fn drop(&mut self) {
+        let previous = self.0.take();
+        let _ = CURRENT.try_with(|scope| {
+            if let (Ok(mut scope), Some(previous)) = (scope.try_borrow_mut(), previous) {
+                *scope = previous;
+            }
+        });
+    }
+}
+
+thread_local! {
+    static CURRENT: RefCell<Scope> = RefCell::new(Scope::default());
+}
+
+/// Configures the scope of the current thread, applied to every event it
+/// captures from now on. Skipped, with a warning, when called from within
+/// another `configure_scope` of the thread. Lets code far from the `Sentry`
+/// handle add context:
+///
+/// ```
+/// sentry::configure_scope(|scope| {
+///     scope.set_tag("tenant".to_string(), "acme".to_string());
+/// });
+/// ```
+pub fn configure_scope<F: FnOnce(&mut Scope)>(f: F) {
+    CURRENT.with(|scope| match scope.try_borrow_mut() {
+        Ok(mut scope) => f(&mut scope),
+        Err(_) => in_use("configure the scope"),
+    });
+}
+
+/// Runs `f` with the scope of the current thread. Skipped, with a warning,
+/// while the scope is being configured, e.g. when capturing an event from
+/// within `configure_scope`.
+pub fn with_current<F: FnOnce(&Scope)>(f: F) {
+    CURRENT.with(|scope| match scope.try_borrow() {
+        Ok(scope) => f(&scope),
+        Err(_) => in_use("read the scope"),
+    });
+}
+
+// a `RefCell` borrow of the scope of this thread failed
+fn in_use(action: &str) {
+    warn!(target: internal::TARGET,
+          "Can't {} while the Sentry scope of this thread is being configured, skipping",
+          action);
+}
+
+/// Like `with_current`, but skips `f` rather than panicking if the scope is
+/// being configured or was already destroyed with its thread, as a panic hook
+/// may find it.
+pub fn try_with_current<F: FnOnce(&Scope)>(f: F) {
+    let _ = CURRENT.try_with(|scope| {
+        if let Ok(scope) = scope.try_borrow() {
+            f(&scope);
+        }
+    });
+}
@@ -0,0 +1,468 @@
+//! Release health: one session per client, from its construction until it is
+//! closed, errored by captured errors and crashed by panics, so Sentry can
+//! report crash-free rates per release. Servers track one session per request
+//! instead, sent as counts per minute so their cost doesn't grow with traffic.
+
+use std::cell::RefCell;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+use uuid::Uuid;
+
+use clock::SharedClock;
+use timestamp::{self, Timestamp};
+use transport::{Message, Transport};
+use SingleWorker;
+
+/// How `Settings::session_tracking` tracks sessions.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SessionMode {
+    /// One session for the life of the client, e.g. for a desktop or command
+    /// line application.
+    Application,
+    /// One session per request, started with `Sentry::start_session` and
+    /// reported as the number of sessions exited, errored or crashed each
+    /// minute, for request-serving processes.
+    Request,
+}
+
+impl Default for SessionMode {
+    fn default() -> SessionMode {
+        SessionMode::Application
+    }
+}
+
+// see https://develop.sentry.dev/sdk/telemetry/sessions/
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SessionStatus {
+    Ok,
+    Exited,
+    Crashed,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SessionAttributes {
+    pub release: String,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub environment: String,
+}
+
+/// The state of a session sent to Sentry, the first with `init` set.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SessionUpdate {
+    pub sid: String,
+    pub init: bool,
+    #[serde(with = "timestamp")]
+    pub started: Timestamp,
+    #[serde(with = "timestamp")]
+    pub timestamp: Timestamp,
+    pub status: SessionStatus,
+    pub errors: u64,
+    pub duration: f64, // seconds
+    pub attrs: SessionAttributes,
+}
+
+struct State {
+    status: SessionStatus,
+    errors: u64,
+    init: bool, // no update was sent yet
+    changed: bool, // since the last update
+}
+
+/// A session, sending an update when it starts (with the first event, so the
+/// worker still spawns lazily), on its first error and when it ends.
+pub struct Session {
+    sid: String,
+    started: Timestamp,
+    started_at: Instant,
+    attrs: SessionAttributes,
+    clock: SharedClock,
+    state: Mutex<State>,
+}
+
+impl Session {
+    pub fn new(release: &str, environment: &str, clock: SharedClock) -> Session {
+        Session {
+            sid: Uuid::new_v4().hyphenated().to_string(),
+            started: clock.now(),
+            started_at: clock.instant(),
+            attrs: SessionAttributes {
+                release: release.to_string(),
+                environment: environment.to_string(),
+            },
+            clock: clock,
+            state: Mutex::new(State {
+                status: SessionStatus::Ok,
+                errors: 0,
+                init: true,
+                changed: true,
+            }),
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, State> {
+        match self.state.lock() {
+            Ok(guard) => guard,
+            Err(poisoned) => poisoned.into_inner(),
+        }
+    }
+
+    /// Counts an error event; the first one is worth an update.
+    pub fn errored(&self) {
+        let mut state = self.lock();
+        if state.status == SessionStatus::Ok {
+            state.errors += 1;
+            state.changed |= state.errors == 1;
+        }
+    }
+
+    /// Ends the session with `status`, unless it ended already.
+    pub fn end(&self, status: SessionStatus) {
+        let mut state = self.lock();
+        if state.status == SessionStatus::Ok {
+            if status == SessionStatus::Crashed {
+                state.errors += 1;
+            }
+            state.status = status;
+            state.changed = true;
+        }
+    }
+
+    /// The update to send, if the session changed since the last one.
+    pub fn take_update(&self) -> Option<SessionUpdate> {
+        let mut state = self.lock();
+        if !state.changed {
+            return None;
+        }
+        let update = SessionUpdate {
+            sid: self.sid.clone(),
+            init: state.init,
+            started: self.started,
+            timestamp: self.clock.now(),
+            status: state.status,
+            errors: state.errors,
+            duration: duration_secs(self.clock.instant().duration_since(self.started_at)),
+            attrs: self.attrs.clone(),
+        };
+        state.init = false;
+        state.changed = false;
+        Some(update)
+    }
+}
+
+fn duration_secs(duration: Duration) -> f64 {
+    duration.as_secs() as f64 + duration.subsec_nanos() as f64 / 1e9
+}
+
+fn is_zero(count: &u64) -> bool {
+    *count == 0
+}
+
+/// The request sessions started in one minute, by how they ended.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SessionCounts {
+    #[serde(with = "timestamp")]
+    pub started: Timestamp,
+    #[serde(skip_serializing_if = "is_zero")]
+    pub exited: u64,
+    #[serde(skip_serializing_if = "is_zero")]
+    pub errored: u64,
+    #[serde(skip_serializing_if = "is_zero")]
+    pub crashed: u64,
+}
+
+/// The counts of request sessions sent to Sentry, for the minutes that ended
+/// since the last ones.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SessionAggregates {
+    pub aggregates: Vec<SessionCounts>,
+    pub attrs: SessionAttributes,
+}
+
+#[derive(Clone, Copy)]
+enum Outcome {
+    Exited,
+    Errored,
+    Crashed,
+}
+
+/// Counts ended request sessions by the minute they started in.
+pub struct Aggregator {
+    attrs: SessionAttributes,
+    clock: SharedClock,
+    counts: Mutex<Vec<SessionCounts>>,
+}
+
+impl Aggregator {
+    pub fn new(release: &str, environment: &str, clock: SharedClock) -> Aggregator {
+        Aggregator {
+            attrs: SessionAttributes {
+                release: release.to_string(),
+                environment: environment.to_string(),
+            },
+            clock: clock,
+            counts: Mutex::new(vec![]),
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Vec<SessionCounts>> {
+        match self.counts.lock() {
+            Ok(guard) => guard,
+            Err(poisoned) => poisoned.into_inner(),
+        }
+    }
+
+    fn record(&self, started: &Timestamp, outcome: Outcome) {
+        let minute = timestamp::minute(started);
+        let mut counts = self.lock();
+        let index = match counts.iter().position(|c| c.started == minute) {
+            Some(index) => index,
+            None => {
+                counts.push(SessionCounts {
+                    started: minute,
+                    exited: 0,
+                    errored: 0,
+                    crashed: 0,
+                });
+                counts.len() - 1
+            }
+        };
+        match outcome {
+            Outcome::Exited => counts[index].exited += 1,
+            Outcome::Errored => counts[index].errored += 1,
+            Outcome::Crashed => counts[index].crashed += 1,
+        }
+    }
+
+    /// The counts of the minutes that ended, or of all of them with `all`.
+    fn take_aggregates(&self, all: bool) -> Option<SessionAggregates> {
+        let current = timestamp::minute(&self.clock.now());
+        let mut counts = self.lock();
+        let (ended, pending) = counts.drain(..).partition(|c| all || c.started < current);
+        *counts = pending;
+        if ended.is_empty() {
+            return None;
+        }
+        Some(SessionAggregates {
+            aggregates: ended,
+            attrs: self.attrs.clone(),
+        })
+    }
+}
+
+/// What a client tracks with `Settings::session_tracking`.
+pub enum Tracking {
+    Application(Session),
+    Request(Aggregator),
+}
+
+struct RequestState {
+    started: Timestamp,
+    errors: AtomicUsize,
+    crashed: AtomicBool,
+    ended: AtomicBool,
+}
+
+impl RequestState {
+    /// Ends the session, unless the panic handler did already.
+    fn end(&self, aggregator: &Aggregator) {
+        if self.ended.swap(true, Ordering::SeqCst) {
+            return;
+        }
+        let outcome = if self.crashed.load(Ordering::SeqCst) {
+            Outcome::Crashed
+        } else if self.errors.load(Ordering::SeqCst) > 0 {
+            Outcome::Errored
+        } else {
+            Outcome::Exited
+        };
+        aggregator.record(&self.started, outcome);
+    }
+}
+
+thread_local! {
+    // the request session of the request served by this thread
+    static CURRENT: RefCell<Option<Arc<RequestState>>> = RefCell::new(None);
+}
+
+fn current_request() -> Option<Arc<RequestState>> {
+    CURRENT.try_with(|current| current.try_borrow().ok().and_then(|current| current.clone()))
+        .ok()
+        .and_then(|current| current)
+}
+
+/// The session of one request, returned by `Sentry::start_session`. It
+/// counts as errored if an error event is captured on its thread while it is
+/// alive, and ends when dropped; a session the panic handler sees panicking
+/// counts as crashed.
+///
+/// ```
+/// # use sentry::Sentry;
+/// # let sentry = Sentry::disabled();
+/// let session = sentry.start_session();
+/// let status = 503; // handle the request
+/// if status >= 500 {
+///     session.errored();
+/// }
+/// ```
+pub struct RequestSession {
+    tracker: Option<Arc<Tracker>>,
+    state: Arc<RequestState>,
+}
+
+impl RequestSession {
+    /// Starts a session on the current thread, counted by `tracker` if it
+    /// tracks request sessions.
+    pub fn start(tracker: Option<&Arc<Tracker>>, started: Timestamp) -> RequestSession {
+        let state = Arc::new(RequestState {
+            started: started,
+            errors: AtomicUsize::new(0),
+            crashed: AtomicBool::new(false),
+            ended: AtomicBool::new(false),
+        });
+        let tracker = tracker.and_then(|tracker| match tracker.tracking {
+            Tracking::Request(_) => Some(tracker.clone()),
+            Tracking::Application(_) => None,
+        });
+        if tracker.is_some() {
+            let _ = CURRENT.try_with(|current| *current.borrow_mut() = Some(state.clone()));
+        }
+        RequestSession {
+            tracker: tracker,
+            state: state,
+        }
+    }
+
+    /// Marks the session errored, e.g. for a failed response no event was
+    /// captured for.
+    pub fn errored(&self) {
+        self.state.errors.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+impl Drop for RequestSession {
+    fn drop(&mut self) {
+        if let Some(ref tracker) = self.tracker {
+            let state = &self.state;
+            let _ = CURRENT.try_with(|current| {
+                let mut current = current.borrow_mut();
+                if current.as_ref().map_or(false, |current| Arc::ptr_eq(current, state)) {
+                    *current = None;
+                }
+            });
+            if let Tracking::Request(ref aggregator) = tracker.tracking {
+                state.end(aggregator);
+            }
+            tracker.report();
+        }
+    }
+}
+
+/// How long dropping the last handle of a client waits for its session to be
+/// reported as exited.
+pub const CLOSE_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// The sessions of a client, shared by its clones. Dropping the last one
+/// ends the application session cleanly, or sends the pending counts of
+/// request sessions.
+pub struct Tracker {
+    tracking: Tracking,
+    transport: Arc<Transport>,
+    worker: Arc<SingleWorker<Message, Arc<Transport>>>,
+}
+
+impl Tracker {
+    pub fn new(tracking: Tracking,
+               transport: Arc<Transport>,
+               worker: Arc<SingleWorker<Message, Arc<Transport>>>)
+               -> Tracker {
+        Tracker {
+            tracking: tracking,
+            transport: transport,
+            worker: worker,
+        }
+    }
+
+    fn queue(&self, message: Message) {
+        self.transport.enqueued();
+        if self.worker.try_work_with(message).is_err() {
+            self.transport.dequeued();
+        }
+    }
+
+    /// Counts an error event against the application session, or the request
+    /// session of the current thread.
+    pub fn errored(&self) {
+        match self.tracking {
+            Tracking::Application(ref session) => session.errored(),
+            Tracking::Request(_) => {
+                if let Some(state) = current_request() {
+                    state.errors.fetch_add(1, Ordering::SeqCst);
+                }
+            }
+        }
+    }
+
+    /// Ends the application session, or the request session of the current
+    /// thread, as crashed and queues what wasn't sent yet, since the process
+    /// may not live to report it.
+    pub fn crashed(&self) {
+        match self.tracking {
+            Tracking::Application(ref session) => {
+                session.end(SessionStatus::Crashed);
+                self.report();
+            }
+            Tracking::Request(ref aggregator) => {
+                if let Some(state) = current_request() {
+                    state.crashed.store(true, Ordering::SeqCst);
+                    state.end(aggregator);
+                }
+                if let Some(aggregates) = aggregator.take_aggregates(true) {
+                    self.queue(Message::Sessions(aggregates));
+                }
+            }
+        }
+    }
+
+    /// Queues the pending update of the application session, or the counts of
+    /// the minutes that ended, if any.
+    pub fn report(&self) {
+        match self.tracking {
+            Tracking::Application(ref session) => {
+                if let Some(update) = session.take_update() {
+                    self.queue(Message::Session(update));
+                }
+            }
+            Tracking::Request(ref aggregator) => {
+                if let Some(aggregates) = aggregator.take_aggregates(false) {
+                    self.queue(Message::Sessions(aggregates));
+                }
+            }
+        }
+    }
+
+    /// Ends the application session, or queues the counts of every minute,
+    /// and waits up to `timeout` for them to be sent.
+    pub fn close(&self, timeout: Duration) -> bool {
+        match self.tracking {
+            Tracking::Application(ref session) => {
+                session.end(SessionStatus::Exited);
+                self.report();
+            }
+            Tracking::Request(ref aggregator) => {
+                if let Some(aggregates) = aggregator.take_aggregates(true) {
+                    self.queue(Message::Sessions(aggregates));
+                }
+            }
+        }
+        self.worker.flush(timeout)
+    }
+}
+
+impl Drop for Tracker {
+    fn drop(&mut self) {
+        self.close(CLOSE_TIMEOUT);
+    }
+}
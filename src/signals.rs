@@ -0,0 +1,310 @@
+//! Running a last function, e.g. a flush, when the process is asked to
+//! terminate or crashes, after which it terminates as it would have.
+
+use errors::*;
+
+#[cfg(unix)]
+use std::fs::File;
+#[cfg(unix)]
+use std::io::{self, Read, Write};
+#[cfg(unix)]
+use std::mem;
+#[cfg(unix)]
+use std::os::unix::io::FromRawFd;
+#[cfg(unix)]
+use std::process;
+#[cfg(unix)]
+use std::ptr;
+#[cfg(unix)]
+use std::sync::atomic::{AtomicBool, AtomicI32, AtomicPtr, Ordering};
+#[cfg(unix)]
+use std::thread;
+#[cfg(unix)]
+use std::time::Duration;
+
+#[cfg(unix)]
+use libc;
+#[cfg(not(unix))]
+use ctrlc;
+
+// the write end of the pipe the signal handler passes the signal through,
+// -1 until `on_termination` installed it
+#[cfg(unix)]
+static PIPE: AtomicI32 = AtomicI32::new(-1);
+
+/// The signals of a crashed thread. SIGABRT is left out, as a panic handler
+/// aborting the process already reported the panic.
+#[cfg(unix)]
+const CRASH_SIGNALS: [libc::c_int; 4] = [libc::SIGSEGV, libc::SIGBUS, libc::SIGILL, libc::SIGFPE];
+
+/// Sentry's names of the registers a crash captures, in the order the crash
+/// handler passes them. None on other platforms.
+#[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+pub const REGISTERS: &'static [&'static str] = &["r8", "r9", "r10", "r11", "r12", "r13", "r14", "r15", "rdi", "rsi",
+                                                 "rbp", "rbx", "rdx", "rax", "rcx", "rsp", "rip"];
+#[cfg(all(target_os = "linux", target_arch = "aarch64"))]
+pub const REGISTERS: &'static [&'static str] = &["x0", "x1", "x2", "x3", "x4", "x5", "x6", "x7", "x8", "x9", "x10",
+                                                 "x11", "x12", "x13", "x14", "x15", "x16", "x17", "x18", "x19",
+                                                 "x20", "x21", "x22", "x23", "x24", "x25", "x26", "x27", "x28", "fp",
+                                                 "lr", "sp", "pc"];
+#[cfg(all(unix, not(all(target_os = "linux", any(target_arch = "x86_64", target_arch = "aarch64")))))]
+pub const REGISTERS: &'static [&'static str] = &[];
+
+/// The signal number followed by the registers, as passed through the pipe.
+#[cfg(unix)]
+const CRASH_RECORD: usize = 1 + 33;
+
+// the write end of the pipe the crash handler passes the crash through, and
+// the read end of the one it waits on until the crash is reported, -1 until
+// `on_crash` installed them
+#[cfg(unix)]
+static CRASH_PIPE: AtomicI32 = AtomicI32::new(-1);
+#[cfg(unix)]
+static REPORTED_PIPE: AtomicI32 = AtomicI32::new(-1);
+#[cfg(unix)]
+static REPORT_TIMEOUT_MS: AtomicI32 = AtomicI32::new(0);
+// the actions of `CRASH_SIGNALS` before `on_crash`, restored once reported
+#[cfg(unix)]
+static PREVIOUS_ACTIONS: AtomicPtr<libc::sigaction> = AtomicPtr::new(ptr::null_mut());
+#[cfg(unix)]
+static CRASHED: AtomicBool = AtomicBool::new(false);
+
+/// A thread's crash: the signal and its registers, named as in `REGISTERS`.
+#[cfg(unix)]
+pub struct Crash {
+    pub signal: libc::c_int,
+    pub registers: Vec<(&'static str, u64)>,
+}
+
+#[cfg(unix)]
+impl Crash {
+    /// The address of the instruction the thread crashed at, if known.
+    pub fn pc(&self) -> Option<u64> {
+        self.registers
+            .iter()
+            .find(|&&(name, _)| name == "rip" || name == "pc")
+            .map(|&(_, value)| value)
+    }
+
+    /// The name of the signal, ex: "SIGSEGV".
+    pub fn signal_name(&self) -> &'static str {
+        match self.signal {
+            libc::SIGSEGV => "SIGSEGV",
+            libc::SIGBUS => "SIGBUS",
+            libc::SIGILL => "SIGILL",
+            libc::SIGFPE => "SIGFPE",
+            _ => "signal",
+        }
+    }
+}
+
+/// Runs `f` on a thread of its own on SIGINT or SIGTERM, then restores the
+/// default action of the signal and raises it again, so the process is
+/// killed by it (exit status 130 or 143 in shells) as without a handler.
+/// Replaces the application's handlers of both signals, and only one can be
+/// installed per process.
+#[cfg(unix)]
+pub fn on_termination<F: FnOnce() + Send + 'static>(f: F) -> Result<()> {
+    let fds = pipe()?;
+    if PIPE.compare_exchange(-1, fds[1], Ordering::SeqCst, Ordering::SeqCst).is_err() {
+        unsafe {
+            libc::close(fds[0]);
+            libc::close(fds[1]);
+        }
+        bail!("a termination handler is already installed");
+    }
+    let mut signals = unsafe { File::from_raw_fd(fds[0]) };
+    thread::Builder::new()
+        .name("sentry-signals".to_string())
+        .spawn(move || {
+            let mut signo = [0u8; 1];
+            if signals.read_exact(&mut signo).is_ok() {
+                f();
+                terminate(signo[0] as libc::c_int);
+            }
+        })
+        .map_err(|e| e.to_string())?;
+    for &signo in &[libc::SIGINT, libc::SIGTERM] {
+        unsafe {
+            let mut action: libc::sigaction = mem::zeroed();
+            action.sa_sigaction = on_signal as extern "C" fn(libc::c_int) as libc::sighandler_t;
+            action.sa_flags = libc::SA_RESTART;
+            libc::sigemptyset(&mut action.sa_mask);
+            if libc::sigaction(signo, &action, ptr::null_mut()) != 0 {
+                bail!(io::Error::last_os_error().to_string());
+            }
+        }
+    }
+    Ok(())
+}
+
+// only async-signal-safe calls in here: the signal thread does the rest
+#[cfg(unix)]
+extern "C" fn on_signal(signo: libc::c_int) {
+    let byte = signo as u8;
+    unsafe {
+        libc::write(PIPE.load(Ordering::SeqCst), &byte as *const u8 as *const libc::c_void, 1);
+    }
+}
+
+/// Runs `f` on a thread of its own when a thread crashes with SIGSEGV,
+/// SIGBUS, SIGILL or SIGFPE, while the crashed thread waits up to `timeout`
+/// for it in the signal handler. The handler then restores the previous
+/// actions of the signals, e.g. the Rust runtime's reporting stack
+/// overflows, which run as the crash happens again. Only the first crash is
+/// passed to `f`, and only one handler can be installed per process.
+#[cfg(unix)]
+pub fn on_crash<F: FnOnce(Crash) + Send + 'static>(timeout: Duration, f: F) -> Result<()> {
+    let crashes = pipe()?;
+    let reported = pipe()?;
+    if CRASH_PIPE.compare_exchange(-1, crashes[1], Ordering::SeqCst, Ordering::SeqCst).is_err() {
+        unsafe {
+            for &fd in crashes.iter().chain(&reported) {
+                libc::close(fd);
+            }
+        }
+        bail!("a crash handler is already installed");
+    }
+    REPORTED_PIPE.store(reported[0], Ordering::SeqCst);
+    let millis = timeout.as_secs() * 1000 + u64::from(timeout.subsec_millis());
+    REPORT_TIMEOUT_MS.store(if millis > i32::MAX as u64 { -1 } else { millis as i32 }, Ordering::SeqCst);
+
+    // read before installing, so the handler never sees them half written
+    let mut previous: Box<[libc::sigaction; 4]> = Box::new(unsafe { mem::zeroed() });
+    for (signo, previous) in CRASH_SIGNALS.iter().zip(previous.iter_mut()) {
+        if unsafe { libc::sigaction(*signo, ptr::null(), previous) } != 0 {
+            bail!(io::Error::last_os_error().to_string());
+        }
+    }
+    PREVIOUS_ACTIONS.store(Box::into_raw(previous) as *mut libc::sigaction, Ordering::SeqCst);
+
+    let mut crashes = unsafe { File::from_raw_fd(crashes[0]) };
+    let mut reported = unsafe { File::from_raw_fd(reported[1]) };
+    thread::Builder::new()
+        .name("sentry-crashes".to_string())
+        .spawn(move || {
+            let mut record = [0u8; CRASH_RECORD * 8];
+            if crashes.read_exact(&mut record).is_ok() {
+                let mut values = record.chunks(8).map(|chunk| {
+                    let mut bytes = [0u8; 8];
+                    bytes.copy_from_slice(chunk);
+                    u64::from_ne_bytes(bytes)
+                });
+                let signal = values.next().unwrap_or(0) as libc::c_int;
+                f(Crash {
+                    signal: signal,
+                    registers: REGISTERS.iter().cloned().zip(values).collect(),
+                });
+                let _ = reported.write_all(&[1]);
+            }
+        })
+        .map_err(|e| e.to_string())?;
+    for &signo in &CRASH_SIGNALS {
+        unsafe {
+            let mut action: libc::sigaction = mem::zeroed();
+            action.sa_sigaction = on_crash_signal as extern "C" fn(libc::c_int, *mut libc::siginfo_t, *mut libc::c_void) as
+                                  libc::sighandler_t;
+            // the alternate stack is the one the Rust runtime sets up for
+            // each thread, so stack overflows are reported too
+            action.sa_flags = libc::SA_SIGINFO | libc::SA_ONSTACK;
+            libc::sigemptyset(&mut action.sa_mask);
+            if libc::sigaction(signo, &action, ptr::null_mut()) != 0 {
+                bail!(io::Error::last_os_error().to_string());
+            }
+        }
+    }
+    Ok(())
+}
+
+// async-signal-safe calls only, like `on_signal`
+#[cfg(unix)]
+extern "C" fn on_crash_signal(signo: libc::c_int, info: *mut libc::siginfo_t, context: *mut libc::c_void) {
+    if !CRASHED.swap(true, Ordering::SeqCst) {
+        let mut record = [0u64; CRASH_RECORD];
+        record[0] = signo as u64;
+        unsafe {
+            registers(context, &mut record[1..]);
+            libc::write(CRASH_PIPE.load(Ordering::SeqCst),
+                        record.as_ptr() as *const libc::c_void,
+                        mem::size_of_val(&record));
+            let mut reported = libc::pollfd {
+                fd: REPORTED_PIPE.load(Ordering::SeqCst),
+                events: libc::POLLIN,
+                revents: 0,
+            };
+            libc::poll(&mut reported, 1, REPORT_TIMEOUT_MS.load(Ordering::SeqCst));
+        }
+    }
+    unsafe {
+        let previous = PREVIOUS_ACTIONS.load(Ordering::SeqCst);
+        match CRASH_SIGNALS.iter().position(|&crash_signal| crash_signal == signo) {
+            Some(index) if !previous.is_null() => libc::sigaction(signo, previous.add(index), ptr::null_mut()),
+            _ => libc::signal(signo, libc::SIG_DFL) as libc::c_int,
+        };
+        // a fault happens again as the handler returns, a signal sent with
+        // kill or raise has to be raised again
+        if (*info).si_code <= 0 {
+            libc::raise(signo);
+        }
+    }
+}
+
+#[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+unsafe fn registers(context: *mut libc::c_void, values: &mut [u64]) {
+    let gregs = &(*(context as *const libc::ucontext_t)).uc_mcontext.gregs;
+    for (value, greg) in values.iter_mut().zip(&gregs[..REGISTERS.len()]) {
+        *value = *greg as u64;
+    }
+}
+
+#[cfg(all(target_os = "linux", target_arch = "aarch64"))]
+unsafe fn registers(context: *mut libc::c_void, values: &mut [u64]) {
+    let mcontext = &(*(context as *const libc::ucontext_t)).uc_mcontext;
+    for (value, register) in values.iter_mut().zip(&mcontext.regs) {
+        *value = *register as u64;
+    }
+    values[31] = mcontext.sp as u64;
+    values[32] = mcontext.pc as u64;
+}
+
+#[cfg(all(unix, not(all(target_os = "linux", any(target_arch = "x86_64", target_arch = "aarch64")))))]
+unsafe fn registers(_context: *mut libc::c_void, _values: &mut [u64]) {}
+
+#[cfg(unix)]
+fn pipe() -> Result<[libc::c_int; 2]> {
+    let mut fds = [0 as libc::c_int; 2];
+    if unsafe { libc::pipe(fds.as_mut_ptr()) } != 0 {
+        bail!(io::Error::last_os_error().to_string());
+    }
+    Ok(fds)
+}
+
+#[cfg(unix)]
+fn terminate(signo: libc::c_int) -> ! {
+    unsafe {
+        libc::signal(signo, libc::SIG_DFL);
+        libc::raise(signo);
+    }
+    // only reached if the signal is blocked
+    process::exit(128 + signo)
+}
+
+/// Runs `f` on ctrl-c, then exits with status 130. Replaces the
+/// application's handler, and only one can be installed per process.
+#[cfg(not(unix))]
+pub fn on_termination<F: FnOnce() + Send + 'static>(f: F) -> Result<()> {
+    use std::sync::Mutex;
+    let f = Mutex::new(Some(f));
+    ctrlc::set_handler(move || {
+            let f = match f.lock() {
+                Ok(mut guard) => guard.take(),
+                Err(poisoned) => poisoned.into_inner().take(),
+            };
+            if let Some(f) = f {
+                f();
+            }
+            ::std::process::exit(130);
+        })
+        .map_err(|e| e.to_string())?;
+    Ok(())
+}
@@ -0,0 +1,29 @@
+//! Delivery of envelopes to a message bus instead of over HTTP.
+
+use std::io;
+
+/// Publishes Sentry envelopes, e.g. to a Kafka topic an internal pipeline
+/// forwards to Sentry Relay. Each envelope carries the DSN of its destination
+/// in its header, so one topic can serve several projects.
+///
+/// Wrapping the producer of a Kafka client:
+///
+/// ```ignore
+/// sentry.set_sink(move |envelope: &[u8]| {
+///     producer.send(BaseRecord::<(), [u8]>::to("sentry-envelopes").payload(envelope))
+///         .map_err(|(e, _)| io::Error::new(io::ErrorKind::Other, e))
+/// });
+/// ```
+pub trait Sink: Send + Sync {
+    /// Hands `envelope` to the bus. An error counts as a failed delivery,
+    /// retried and reported like an HTTP one.
+    fn publish(&self, envelope: &[u8]) -> io::Result<()>;
+}
+
+impl<F> Sink for F
+    where F: Fn(&[u8]) -> io::Result<()> + Send + Sync
+{
+    fn publish(&self, envelope: &[u8]) -> io::Result<()> {
+        self(envelope)
+    }
+}
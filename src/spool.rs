@@ -0,0 +1,242 @@
+//! Undelivered events persisted to disk, so they survive restarts.
+
+#[cfg(feature = "spool-encryption")]
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{ErrorKind, Read, Write};
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+#[cfg(feature = "spool-encryption")]
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+#[cfg(feature = "spool-encryption")]
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng};
+
+use errors::*;
+use internal;
+use Event;
+
+/// Nonce prepended to every encrypted spool file.
+#[cfg(feature = "spool-encryption")]
+const NONCE_LENGTH: usize = 12;
+
+/// A 256-bit key encrypting the spool at rest with ChaCha20-Poly1305.
+#[cfg(feature = "spool-encryption")]
+#[derive(Clone, PartialEq)]
+pub struct SpoolKey(pub [u8; 32]);
+
+#[cfg(feature = "spool-encryption")]
+impl fmt::Debug for SpoolKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("SpoolKey(..)")
+    }
+}
+
+/// A directory holding one `<sequence>-<event_id>.json` file per undelivered
+/// event, the sequence number counting up as events are spooled.
+///
+/// When it grows past `max_files` or `max_bytes`, the events with the lowest
+/// sequence numbers are evicted so a long outage can't fill the disk. File
+/// modification times aren't used, as they can be coarse, go backwards with
+/// the clock or be touched by backups.
+pub struct Spool {
+    dir: PathBuf,
+    max_files: Option<usize>,
+    max_bytes: Option<u64>,
+    evicted: AtomicUsize,
+    // the sequence number of the next event stored, 0 until read from the
+    // files left by an earlier process
+    next: Mutex<u64>,
+    #[cfg(feature = "spool-encryption")]
+    cipher: Option<ChaCha20Poly1305>,
+}
+
+impl Spool {
+    pub fn new(dir: PathBuf, max_files: Option<usize>, max_bytes: Option<u64>) -> Spool {
+        Spool {
+            dir: dir,
+            max_files: max_files,
+            max_bytes: max_bytes,
+            evicted: AtomicUsize::new(0),
+            next: Mutex::new(0),
+            #[cfg(feature = "spool-encryption")]
+            cipher: None,
+        }
+    }
+
+    /// Encrypts events written from now on with `key`. Events spooled in
+    /// plaintext before can't be read back anymore.
+    #[cfg(feature = "spool-encryption")]
+    pub fn with_key(mut self, key: &SpoolKey) -> Spool {
+        self.cipher = Some(ChaCha20Poly1305::new(Key::from_slice(&key.0)));
+        self
+    }
+
+    #[cfg(feature = "spool-encryption")]
+    fn seal(&self, plaintext: Vec<u8>) -> Result<Vec<u8>> {
+        let cipher = match self.cipher {
+            Some(ref cipher) => cipher,
+            None => return Ok(plaintext),
+        };
+        let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let mut sealed = nonce.to_vec();
+        sealed.extend(cipher.encrypt(&nonce, &plaintext[..]).map_err(|_| "Failed to encrypt spooled event")?);
+        Ok(sealed)
+    }
+
+    #[cfg(not(feature = "spool-encryption"))]
+    fn seal(&self, plaintext: Vec<u8>) -> Result<Vec<u8>> {
+        Ok(plaintext)
+    }
+
+    #[cfg(feature = "spool-encryption")]
+    fn open(&self, sealed: Vec<u8>) -> Result<Vec<u8>> {
+        let cipher = match self.cipher {
+            Some(ref cipher) => cipher,
+            None => return Ok(sealed),
+        };
+        if sealed.len() < NONCE_LENGTH {
+            bail!("Spooled event is too short to be encrypted");
+        }
+        let (nonce, ciphertext) = sealed.split_at(NONCE_LENGTH);
+        Ok(cipher.decrypt(Nonce::from_slice(nonce), ciphertext)
+            .map_err(|_| "Failed to decrypt spooled event, was it written with another key?")?)
+    }
+
+    #[cfg(not(feature = "spool-encryption"))]
+    fn open(&self, sealed: Vec<u8>) -> Result<Vec<u8>> {
+        Ok(sealed)
+    }
+
+    /// How many events were evicted to respect the limits.
+    pub fn evicted(&self) -> usize {
+        self.evicted.load(Ordering::Relaxed)
+    }
+
+    fn path(&self, sequence: u64, event_id: &str) -> Result<PathBuf> {
+        if event_id.is_empty() || !event_id.chars().all(|c| c.is_digit(16)) {
+            bail!("Can't spool event with id {:?}", event_id);
+        }
+        // zero-padded, so a listing sorts the files like their numbers
+        Ok(self.dir.join(format!("{:020}-{}.json", sequence, event_id)))
+    }
+
+    /// Writes `e` to the spool, replacing any earlier copy, which keeps its
+    /// place in line.
+    pub fn store(&self, e: &Event) -> Result<()> {
+        let mut next = match self.next.lock() {
+            Ok(guard) => guard,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        let entries = self.entries()?;
+        if *next == 0 {
+            *next = entries.iter().map(|&(sequence, _, _, _)| sequence).max().unwrap_or(0) + 1;
+        }
+        let earlier = entries.into_iter().find(|&(_, ref event_id, _, _)| *event_id == e.event_id);
+        let sequence = match earlier {
+            Some((sequence, _, _, _)) => sequence,
+            None => {
+                *next += 1;
+                *next - 1
+            }
+        };
+        let path = self.path(sequence, &e.event_id)?;
+        fs::create_dir_all(&self.dir)?;
+        // write aside and rename, so a crash never leaves a truncated event
+        let partial = path.with_extension("json.partial");
+        File::create(&partial)?.write_all(&self.seal(e.to_json()?.into_bytes())?)?;
+        fs::rename(&partial, &path)?;
+        // an earlier copy spooled by a version naming files by event id only
+        if let Some((_, _, _, earlier)) = earlier {
+            if earlier != path {
+                fs::remove_file(&earlier)?;
+            }
+        }
+        drop(next);
+        self.evict()
+    }
+
+    fn evict(&self) -> Result<()> {
+        if self.max_files.is_none() && self.max_bytes.is_none() {
+            return Ok(());
+        }
+        let entries = self.entries()?;
+        let mut files = entries.len();
+        let mut bytes = entries.iter().map(|&(_, _, size, _)| size).sum::<u64>();
+        for (_, _, size, path) in entries {
+            if self.max_files.map_or(true, |max| files <= max) &&
+               self.max_bytes.map_or(true, |max| bytes <= max) {
+                break;
+            }
+            fs::remove_file(&path)?;
+            files -= 1;
+            bytes -= size;
+            self.evicted.fetch_add(1, Ordering::Relaxed);
+            warn!(target: internal::TARGET, "Sentry spool is full, evicted {}", path.display());
+        }
+        Ok(())
+    }
+
+    /// Sequence number, event id, size and path of every spooled event,
+    /// oldest first. Files named by event id only, by earlier versions, come
+    /// first as sequence 0.
+    fn entries(&self) -> Result<Vec<(u64, String, u64, PathBuf)>> {
+        let dir = match fs::read_dir(&self.dir) {
+            Ok(dir) => dir,
+            Err(ref e) if e.kind() == ErrorKind::NotFound => return Ok(vec![]),
+            Err(e) => return Err(e.into()),
+        };
+        let mut entries = vec![];
+        for entry in dir {
+            let path = entry?.path();
+            if path.extension().map_or(true, |extension| extension != "json") {
+                continue;
+            }
+            let (sequence, event_id) = {
+                let stem = path.file_stem().and_then(|stem| stem.to_str()).unwrap_or("");
+                match stem.find('-') {
+                    Some(dash) => (stem[..dash].parse().unwrap_or(0), stem[dash + 1..].to_string()),
+                    None => (0, stem.to_string()),
+                }
+            };
+            let size = fs::metadata(&path)?.len();
+            entries.push((sequence, event_id, size, path));
+        }
+        entries.sort();
+        Ok(entries)
+    }
+
+    pub fn remove(&self, event_id: &str) -> Result<()> {
+        for (_, spooled, _, path) in self.entries()? {
+            if spooled != event_id {
+                continue;
+            }
+            match fs::remove_file(path) {
+                Err(ref e) if e.kind() == ErrorKind::NotFound => {}
+                result => result?,
+            }
+        }
+        Ok(())
+    }
+
+    /// Reads back every spooled event, oldest first. Unreadable files are
+    /// skipped and left in place.
+    pub fn load(&self) -> Result<Vec<Event>> {
+        let mut events = vec![];
+        for (_, _, _, path) in self.entries()? {
+            let mut contents = vec![];
+            let loaded = File::open(&path)
+                .and_then(|mut file| file.read_to_end(&mut contents))
+                .map_err(Error::from)
+                .and_then(|_| self.open(contents))
+                .and_then(|json| String::from_utf8(json).map_err(|e| e.to_string().into()))
+                .and_then(|json| Event::from_json(&json));
+            match loaded {
+                Ok(e) => events.push(e),
+                Err(e) => warn!(target: internal::TARGET, "Skipping spooled Sentry event {}: {}", path.display(), e),
+            }
+        }
+        Ok(events)
+    }
+}
@@ -0,0 +1,73 @@
+//! Counters of the client's own activity, reported through the `metrics`
+//! facade with the `metrics` feature, so the reporter's health shows up on
+//! the dashboards of whichever recorder (Prometheus exporter, statsd...) the
+//! application installed. Without the feature they compile to nothing.
+
+use std::time::Duration;
+
+/// An event accepted by a destination.
+#[cfg(feature = "metrics")]
+pub fn sent() {
+    ::metrics::counter!("sentry_events_sent_total").increment(1);
+}
+
+/// A post to a destination that failed or was refused.
+#[cfg(feature = "metrics")]
+pub fn failed() {
+    ::metrics::counter!("sentry_events_failed_total").increment(1);
+}
+
+/// An event given up on, ex: "undelivered" (failed or rate limited, with
+/// no retry configured), "rejected" (with a status retrying won't change),
+/// "duplicate", "queue_full", "sampled", "ignored_logger".
+#[cfg(feature = "metrics")]
+pub fn dropped(reason: &'static str) {
+    ::metrics::counter!("sentry_events_dropped_total", "reason" => reason).increment(1);
+}
+
+/// Items removed from an event to fit the payload limit, ex: `count`
+/// "breadcrumbs" or "frames".
+#[cfg(feature = "metrics")]
+pub fn trimmed(what: &'static str, count: usize) {
+    ::metrics::counter!("sentry_trimmed_total", "field" => what).increment(count as u64);
+}
+
+/// Events waiting for the worker.
+#[cfg(feature = "metrics")]
+pub fn queue_depth(depth: usize) {
+    ::metrics::gauge!("sentry_queue_depth").set(depth as f64);
+}
+
+/// Undelivered events held for retry.
+#[cfg(feature = "metrics")]
+pub fn pending(count: usize) {
+    ::metrics::gauge!("sentry_pending_events").set(count as f64);
+}
+
+/// How long a post to a destination took, whatever its outcome.
+#[cfg(feature = "metrics")]
+pub fn send_latency(elapsed: Duration) {
+    let seconds = elapsed.as_secs() as f64 + elapsed.subsec_nanos() as f64 / 1e9;
+    ::metrics::histogram!("sentry_send_duration_seconds").record(seconds);
+}
+
+#[cfg(not(feature = "metrics"))]
+pub fn sent() {}
+
+#[cfg(not(feature = "metrics"))]
+pub fn failed() {}
+
+#[cfg(not(feature = "metrics"))]
+pub fn dropped(_reason: &'static str) {}
+
+#[cfg(not(feature = "metrics"))]
+pub fn trimmed(_what: &'static str, _count: usize) {}
+
+#[cfg(not(feature = "metrics"))]
+pub fn queue_depth(_depth: usize) {}
+
+#[cfg(not(feature = "metrics"))]
+pub fn pending(_count: usize) {}
+
+#[cfg(not(feature = "metrics"))]
+pub fn send_latency(_elapsed: Duration) {}
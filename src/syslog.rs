@@ -0,0 +1,102 @@
+//! Syslog as a fallback destination, so events still leave a trace during a
+//! Sentry outage.
+
+use std::env;
+use std::path::{Path, PathBuf};
+use std::process;
+
+use errors::*;
+use timestamp;
+use Event;
+
+/// Where the local syslog daemon listens by default.
+pub const DEFAULT_PATH: &'static str = "/dev/log";
+
+/// The IANA example enterprise number, qualifying the SD-ID of our
+/// structured data as RFC 5424 requires.
+const SD_ID: &'static str = "sentry@32473";
+
+/// Severity of a level, `user` being the facility.
+fn priority(level: &str) -> u8 {
+    let severity = match level {
+        "fatal" => 2, // critical
+        "error" => 3,
+        "warning" => 4,
+        "debug" => 7,
+        _ => 6, // informational
+    };
+    8 + severity
+}
+
+/// Escapes a structured data parameter value.
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace(']', "\\]")
+}
+
+/// Header fields are printable ASCII without spaces, `-` if empty.
+fn header_field(value: &str, max_len: usize) -> String {
+    let field: String = value.chars().filter(|c| c.is_ascii_graphic()).take(max_len).collect();
+    if field.is_empty() { "-".to_string() } else { field }
+}
+
+/// Formats `e` as an RFC 5424 message, its ids and origin as structured data:
+///
+/// `<11>1 2017-08-01T12:00:00.000000Z web-1 myapp 4242 error [sentry@32473
+/// event_id="..." logger="payments" release="1.2.0"] Payment failed`
+pub fn format(e: &Event, hostname: &str, app_name: &str) -> String {
+    let mut data = format!("[{} event_id=\"{}\" logger=\"{}\"", SD_ID, escape(e.event_id()), escape(e.logger()));
+    for &(name, value) in &[("culprit", e.culprit()),
+                            ("release", e.release()),
+                            ("environment", e.environment()),
+                            ("transaction", e.transaction())] {
+        if let Some(value) = value {
+            data.push_str(&format!(" {}=\"{}\"", name, escape(value)));
+        }
+    }
+    data.push(']');
+    format!("<{}>1 {}Z {} {} {} {} {} {}",
+            priority(e.level()),
+            timestamp::format(e.timestamp()),
+            header_field(hostname, 255),
+            header_field(app_name, 48),
+            process::id(),
+            header_field(e.level(), 32),
+            data,
+            e.message())
+}
+
+/// Writes events to a syslog socket.
+pub struct Syslog {
+    path: PathBuf,
+    hostname: String,
+    app_name: String,
+}
+
+impl Syslog {
+    pub fn new(path: Option<&Path>, hostname: &str) -> Syslog {
+        let app_name = env::current_exe()
+            .ok()
+            .and_then(|exe| exe.file_name().and_then(|name| name.to_str()).map(|name| name.to_string()))
+            .unwrap_or_default();
+        Syslog {
+            path: path.map_or_else(|| PathBuf::from(DEFAULT_PATH), |path| path.to_path_buf()),
+            hostname: hostname.to_string(),
+            app_name: app_name,
+        }
+    }
+
+    /// Sends `e` as one datagram.
+    #[cfg(unix)]
+    pub fn send(&self, e: &Event) -> Result<()> {
+        use std::os::unix::net::UnixDatagram;
+
+        let socket = UnixDatagram::unbound()?;
+        socket.send_to(format(e, &self.hostname, &self.app_name).as_bytes(), &self.path)?;
+        Ok(())
+    }
+
+    #[cfg(not(unix))]
+    pub fn send(&self, _e: &Event) -> Result<()> {
+        bail!("syslog is only supported on Unix")
+    }
+}
@@ -0,0 +1,46 @@
+//! Sentry's limits on tags, enforced client-side since the server silently
+//! drops tags exceeding them.
+
+use std::collections::HashMap;
+
+pub const MAX_TAG_KEY_LENGTH: usize = 32;
+pub const MAX_TAG_VALUE_LENGTH: usize = 200;
+
+/// Replaces line breaks with spaces and truncates keys and values to their
+/// maximum length, returning the original keys of the tags changed. A changed
+/// key that would replace another tag ends in a hash of the original instead,
+/// so both are kept.
+pub fn normalize(tags: &mut HashMap<String, String>) -> Vec<String> {
+    let mut invalid: Vec<String> = tags.iter()
+        .filter(|&(key, value)| normalized(key, MAX_TAG_KEY_LENGTH) != *key ||
+                                normalized(value, MAX_TAG_VALUE_LENGTH) != *value)
+        .map(|(key, _)| key.clone())
+        .collect();
+    // sorted, so the same tags always end up with the same keys
+    invalid.sort();
+    let removed: Vec<(String, String)> = invalid.iter()
+        .filter_map(|key| tags.remove(key).map(|value| (key.clone(), value)))
+        .collect();
+    for (key, value) in removed {
+        let mut normalized_key = normalized(&key, MAX_TAG_KEY_LENGTH);
+        if tags.contains_key(&normalized_key) {
+            let suffix = format!("-{:06x}", fnv1a(&key) & 0xff_ffff);
+            normalized_key = normalized(&key, MAX_TAG_KEY_LENGTH - suffix.len()) + &suffix;
+        }
+        tags.insert(normalized_key, normalized(&value, MAX_TAG_VALUE_LENGTH));
+    }
+    invalid
+}
+
+/// A hash that stays the same across runs and Rust versions, unlike the
+/// standard library's.
+fn fnv1a(s: &str) -> u64 {
+    s.bytes().fold(0xcbf2_9ce4_8422_2325, |hash, byte| (hash ^ u64::from(byte)).wrapping_mul(0x100_0000_01b3))
+}
+
+fn normalized(s: &str, max_length: usize) -> String {
+    s.chars()
+        .map(|c| if c == '\r' || c == '\n' { ' ' } else { c })
+        .take(max_length)
+        .collect()
+}
@@ -0,0 +1,78 @@
+//! The threads of the process, attached to fatal events when
+//! `Settings::thread_dump` is set.
+
+use std::fs;
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+// see https://develop.sentry.dev/sdk/event-payloads/threads/
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Thread {
+    id: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    name: Option<String>,
+    /// The thread the event was captured on.
+    #[serde(default)]
+    current: bool,
+}
+
+impl Thread {
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_ref().map(|n| &n[..])
+    }
+
+    pub fn is_current(&self) -> bool {
+        self.current
+    }
+}
+
+/// Lists the threads of the process with their names, from `/proc` on Linux
+/// and nothing elsewhere.
+///
+/// Only the capturing thread gets a stack trace, the event's own: unwinding
+/// other threads needs an external unwinder (ptrace of a forked helper) this
+/// client doesn't ship, but their names alone often show what a deadlocked or
+/// starved process was doing.
+pub fn dump() -> Vec<Thread> {
+    let current = fs::read_link("/proc/thread-self")
+        .ok()
+        .and_then(|path| path.file_name().and_then(|tid| tid.to_str()).and_then(|tid| tid.parse().ok()));
+    let tasks = match fs::read_dir("/proc/self/task") {
+        Ok(tasks) => tasks,
+        Err(_) => return vec![],
+    };
+    let mut threads: Vec<Thread> = tasks.filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            let id = entry.file_name().to_str().and_then(|tid| tid.parse().ok())?;
+            let name = fs::read_to_string(entry.path().join("comm")).ok().map(|comm| comm.trim().to_string());
+            Some(Thread {
+                id: id,
+                name: name,
+                current: Some(id) == current,
+            })
+        })
+        .collect();
+    threads.sort_by_key(|thread| thread.id);
+    threads
+}
+
+/// Threads are sent as `{"values": [...]}`.
+pub fn serialize<S: Serializer>(threads: &[Thread], serializer: S) -> Result<S::Ok, S::Error> {
+    #[derive(Serialize)]
+    struct Values<'a> {
+        values: &'a [Thread],
+    }
+    Values { values: threads }.serialize(serializer)
+}
+
+pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<Thread>, D::Error> {
+    #[derive(Deserialize)]
+    struct Values {
+        values: Vec<Thread>,
+    }
+    Values::deserialize(deserializer).map(|v| v.values)
+}
@@ -0,0 +1,311 @@
+//! Event timestamps, serialized the way Sentry expects them.
+
+use std::fmt;
+use std::time::{Duration, SystemTime};
+#[cfg(not(feature = "chrono"))]
+use std::time::UNIX_EPOCH;
+
+use serde::{Deserialize, Deserializer, Serializer};
+use serde::de::Error;
+
+#[cfg(feature = "chrono")]
+use chrono::{DateTime, NaiveDateTime, Utc};
+
+/// Either form a timestamp is read from, the other being the number of
+/// seconds since the UNIX epoch, as sent with `Settings::epoch_timestamps`.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Serialized {
+    Formatted(String),
+    Epoch(f64),
+}
+
+/// ISO 8601 format, without a timezone ex: "2011-05-02T17:41:36". Fractional
+/// seconds are appended so events from the same second stay ordered.
+const FORMAT: &'static str = "%Y-%m-%dT%H:%M:%S";
+
+/// The seconds since the UNIX epoch `time` converts without failing, from
+/// 0000-01-01T00:00:00 to 9999-12-31T23:59:59.
+#[cfg(not(feature = "chrono"))]
+const UNIX_RANGE: (i64, i64) = (-62167219200, 253402300799);
+
+/// The point in time an event happened, always in UTC. Converts from and to
+/// `SystemTime`, and with the `chrono` feature from and to `DateTime<Utc>`,
+/// whichever library formats it.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Timestamp(Inner);
+
+#[cfg(feature = "chrono")]
+type Inner = DateTime<Utc>;
+
+#[cfg(not(feature = "chrono"))]
+type Inner = ::time::Tm;
+
+impl Timestamp {
+    #[cfg(feature = "chrono")]
+    pub fn now() -> Timestamp {
+        Timestamp(Utc::now())
+    }
+
+    #[cfg(not(feature = "chrono"))]
+    pub fn now() -> Timestamp {
+        Timestamp(::time::now_utc())
+    }
+
+    /// Seconds since the UNIX epoch, with microsecond precision.
+    pub fn epoch(&self) -> f64 {
+        epoch(self)
+    }
+}
+
+impl fmt::Display for Timestamp {
+    /// ISO 8601 with microsecond precision, ex: "2011-05-02T17:41:36.123456"
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&format(self))
+    }
+}
+
+impl fmt::Debug for Timestamp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Timestamp({})", format(self))
+    }
+}
+
+#[cfg(feature = "chrono")]
+impl From<DateTime<Utc>> for Timestamp {
+    fn from(time: DateTime<Utc>) -> Timestamp {
+        Timestamp(time)
+    }
+}
+
+#[cfg(feature = "chrono")]
+impl From<Timestamp> for DateTime<Utc> {
+    fn from(timestamp: Timestamp) -> DateTime<Utc> {
+        timestamp.0
+    }
+}
+
+/// E.g. the start of a span recorded by another tracer.
+#[cfg(feature = "chrono")]
+impl From<SystemTime> for Timestamp {
+    fn from(time: SystemTime) -> Timestamp {
+        Timestamp(DateTime::from(time))
+    }
+}
+
+/// E.g. the start of a span recorded by another tracer.
+#[cfg(not(feature = "chrono"))]
+impl From<SystemTime> for Timestamp {
+    fn from(time: SystemTime) -> Timestamp {
+        let spec = match time.duration_since(UNIX_EPOCH) {
+            Ok(since) => ::time::Timespec::new(since.as_secs() as i64, since.subsec_nanos() as i32),
+            Err(before) => {
+                let before = before.duration();
+                ::time::Timespec::new(0, 0) -
+                ::time::Duration::from_std(before).unwrap_or_else(|_| ::time::Duration::zero())
+            }
+        };
+        Timestamp(::time::at_utc(spec))
+    }
+}
+
+#[cfg(feature = "chrono")]
+impl From<Timestamp> for SystemTime {
+    fn from(timestamp: Timestamp) -> SystemTime {
+        SystemTime::from(timestamp.0)
+    }
+}
+
+#[cfg(not(feature = "chrono"))]
+impl From<Timestamp> for SystemTime {
+    fn from(timestamp: Timestamp) -> SystemTime {
+        let spec = timestamp.0.to_timespec();
+        let nanos = Duration::new(0, spec.nsec as u32);
+        if spec.sec >= 0 {
+            UNIX_EPOCH + Duration::from_secs(spec.sec as u64) + nanos
+        } else {
+            UNIX_EPOCH - Duration::from_secs(spec.sec.unsigned_abs()) + nanos
+        }
+    }
+}
+
+pub fn now() -> Timestamp {
+    Timestamp::now()
+}
+
+/// The timestamp `seconds` after the UNIX epoch, if it can be represented.
+#[cfg(feature = "chrono")]
+pub fn from_unix(seconds: i64) -> Option<Timestamp> {
+    use chrono::TimeZone;
+    Utc.timestamp_opt(seconds, 0).single().map(Timestamp)
+}
+
+/// The timestamp `seconds` after the UNIX epoch, if it can be represented.
+#[cfg(not(feature = "chrono"))]
+pub fn from_unix(seconds: i64) -> Option<Timestamp> {
+    if seconds < UNIX_RANGE.0 || seconds > UNIX_RANGE.1 {
+        return None;
+    }
+    Some(Timestamp(::time::at_utc(::time::Timespec::new(seconds, 0))))
+}
+
+/// `timestamp` moved forward by `by`.
+#[cfg(feature = "chrono")]
+pub fn add(timestamp: &Timestamp, by: Duration) -> Timestamp {
+    Timestamp(timestamp.0 + ::chrono::Duration::from_std(by).unwrap_or_else(|_| ::chrono::Duration::zero()))
+}
+
+/// `timestamp` moved forward by `by`.
+#[cfg(not(feature = "chrono"))]
+pub fn add(timestamp: &Timestamp, by: Duration) -> Timestamp {
+    Timestamp(timestamp.0 + ::time::Duration::from_std(by).unwrap_or_else(|_| ::time::Duration::zero()))
+}
+
+/// The start of the minute of `timestamp`, which aggregated sessions are
+/// counted by.
+#[cfg(feature = "chrono")]
+pub fn minute(timestamp: &Timestamp) -> Timestamp {
+    use chrono::Timelike;
+    Timestamp(timestamp.0.with_nanosecond(0).and_then(|t| t.with_second(0)).unwrap_or(timestamp.0))
+}
+
+/// The start of the minute of `timestamp`, which aggregated sessions are
+/// counted by.
+#[cfg(not(feature = "chrono"))]
+pub fn minute(timestamp: &Timestamp) -> Timestamp {
+    let mut minute = timestamp.0;
+    minute.tm_sec = 0;
+    minute.tm_nsec = 0;
+    Timestamp(minute)
+}
+
+/// Seconds since the UNIX epoch, with microsecond precision like `format`.
+#[cfg(feature = "chrono")]
+pub fn epoch(timestamp: &Timestamp) -> f64 {
+    timestamp.0.timestamp() as f64 + timestamp.0.timestamp_subsec_micros() as f64 / 1e6
+}
+
+/// Seconds since the UNIX epoch, with microsecond precision like `format`.
+#[cfg(not(feature = "chrono"))]
+pub fn epoch(timestamp: &Timestamp) -> f64 {
+    let spec = timestamp.0.to_timespec();
+    spec.sec as f64 + (spec.nsec / 1000) as f64 / 1e6
+}
+
+#[cfg(feature = "chrono")]
+fn from_epoch(epoch: f64) -> Option<Timestamp> {
+    use chrono::TimeZone;
+    let micros = (epoch * 1e6).round() as i64;
+    let nanos = micros.rem_euclid(1_000_000) as u32 * 1000;
+    Utc.timestamp_opt(micros.div_euclid(1_000_000), nanos).single().map(Timestamp)
+}
+
+#[cfg(not(feature = "chrono"))]
+fn from_epoch(epoch: f64) -> Option<Timestamp> {
+    let micros = (epoch * 1e6).round() as i64;
+    let seconds = micros.div_euclid(1_000_000);
+    if seconds < UNIX_RANGE.0 || seconds > UNIX_RANGE.1 {
+        return None;
+    }
+    Some(Timestamp(::time::at_utc(::time::Timespec::new(seconds, micros.rem_euclid(1_000_000) as i32 * 1000))))
+}
+
+/// `timestamp` moved by `seconds`, forward or back.
+#[cfg(feature = "chrono")]
+pub fn shift(timestamp: &Timestamp, seconds: i64) -> Timestamp {
+    Timestamp(timestamp.0 + ::chrono::Duration::seconds(seconds))
+}
+
+/// `timestamp` moved by `seconds`, forward or back.
+#[cfg(not(feature = "chrono"))]
+pub fn shift(timestamp: &Timestamp, seconds: i64) -> Timestamp {
+    Timestamp(timestamp.0 + ::time::Duration::seconds(seconds))
+}
+
+/// Parses the `Date` header of an HTTP response, ex: "Tue, 01 Aug 2017
+/// 12:00:00 GMT".
+#[cfg(feature = "chrono")]
+pub fn parse_http_date(s: &str) -> Option<Timestamp> {
+    DateTime::parse_from_rfc2822(s.trim()).ok().map(|date| Timestamp(date.with_timezone(&Utc)))
+}
+
+/// Parses the `Date` header of an HTTP response, ex: "Tue, 01 Aug 2017
+/// 12:00:00 GMT".
+#[cfg(not(feature = "chrono"))]
+pub fn parse_http_date(s: &str) -> Option<Timestamp> {
+    ::time::strptime(s.trim(), "%a, %d %b %Y %H:%M:%S GMT").ok().map(Timestamp)
+}
+
+/// Formats with microsecond precision, ex: "2011-05-02T17:41:36.123456"
+#[cfg(feature = "chrono")]
+pub fn format(timestamp: &Timestamp) -> String {
+    format!("{}{}", timestamp.0.format(FORMAT), timestamp.0.format("%.6f"))
+}
+
+/// Formats with microsecond precision, ex: "2011-05-02T17:41:36.123456"
+#[cfg(not(feature = "chrono"))]
+pub fn format(timestamp: &Timestamp) -> String {
+    ::time::strftime(FORMAT, &timestamp.0)
+        .map(|s| format!("{}.{:06}", s, timestamp.0.tm_nsec / 1000))
+        .unwrap_or_default()
+}
+
+/// Parses timestamps with or without fractional seconds.
+#[cfg(feature = "chrono")]
+pub fn parse(s: &str) -> Option<Timestamp> {
+    NaiveDateTime::parse_from_str(s, "%Y-%m-%dT%H:%M:%S%.f")
+        .ok()
+        .map(|naive| Timestamp(DateTime::from_naive_utc_and_offset(naive, Utc)))
+}
+
+/// Parses timestamps with or without fractional seconds.
+#[cfg(not(feature = "chrono"))]
+pub fn parse(s: &str) -> Option<Timestamp> {
+    let mut parts = s.splitn(2, '.');
+    let mut timestamp = match ::time::strptime(parts.next().unwrap_or(""), FORMAT) {
+        Ok(timestamp) => timestamp,
+        Err(_) => return None,
+    };
+    if let Some(fraction) = parts.next() {
+        if fraction.is_empty() || fraction.len() > 9 || !fraction.chars().all(|c| c.is_digit(10)) {
+            return None;
+        }
+        let digits = format!("{:0<9}", fraction);
+        timestamp.tm_nsec = match digits.parse() {
+            Ok(nsec) => nsec,
+            Err(_) => return None,
+        };
+    }
+    Some(Timestamp(timestamp))
+}
+
+pub fn serialize<S: Serializer>(timestamp: &Timestamp, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&format(timestamp))
+}
+
+impl Serialized {
+    fn timestamp<E: Error>(self) -> Result<Timestamp, E> {
+        match self {
+            Serialized::Formatted(s) => parse(&s).ok_or_else(|| E::custom(format!("invalid timestamp: {}", s))),
+            Serialized::Epoch(epoch) => from_epoch(epoch).ok_or_else(|| E::custom(format!("invalid timestamp: {}", epoch))),
+        }
+    }
+}
+
+pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Timestamp, D::Error> {
+    Serialized::deserialize(deserializer)?.timestamp()
+}
+
+pub fn serialize_option<S: Serializer>(timestamp: &Option<Timestamp>, serializer: S) -> Result<S::Ok, S::Error> {
+    match *timestamp {
+        Some(ref timestamp) => serialize(timestamp, serializer),
+        None => serializer.serialize_none(),
+    }
+}
+
+pub fn deserialize_option<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Timestamp>, D::Error> {
+    match Option::<Serialized>::deserialize(deserializer)? {
+        Some(serialized) => serialized.timestamp().map(Some),
+        None => Ok(None),
+    }
+}
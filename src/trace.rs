@@ -0,0 +1,119 @@
+//! The trace an event happens in, linking errors to performance traces, and
+//! the spans of transactions.
+
+use std::collections::HashMap;
+
+use serde_json::Value;
+use uuid::Uuid;
+
+use timestamp::{self, Timestamp};
+
+/// Identifies the span active on a scope, sent as the `trace` context of
+/// events so Sentry links them to their trace.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TraceContext {
+    pub trace_id: String, // 32 hex characters
+    pub span_id: String, // 16 hex characters
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parent_span_id: Option<String>,
+    /// ex: "http.server" or "db.query"
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub op: Option<String>,
+}
+
+fn span_id() -> String {
+    Uuid::new_v4().simple().to_string()[..16].to_string()
+}
+
+fn is_hex(s: &str, len: usize) -> bool {
+    s.len() == len && s.chars().all(|c| c.is_digit(16))
+}
+
+impl TraceContext {
+    /// The root span of a new trace.
+    pub fn new(op: Option<&str>) -> TraceContext {
+        TraceContext {
+            trace_id: Uuid::new_v4().simple().to_string(),
+            span_id: span_id(),
+            parent_span_id: None,
+            op: op.map(|op| op.to_string()),
+        }
+    }
+
+    /// A span of the same trace, started from this one.
+    pub fn child(&self, op: Option<&str>) -> TraceContext {
+        TraceContext {
+            trace_id: self.trace_id.clone(),
+            span_id: span_id(),
+            parent_span_id: Some(self.span_id.clone()),
+            op: op.map(|op| op.to_string()),
+        }
+    }
+
+    /// Continues the trace of an incoming `sentry-trace` header,
+    /// `<trace_id>-<span_id>[-<sampled>]`, as a child of its span.
+    pub fn from_sentry_trace(header: &str, op: Option<&str>) -> Option<TraceContext> {
+        let mut parts = header.trim().split('-');
+        match (parts.next(), parts.next()) {
+            (Some(trace_id), Some(span_id)) if is_hex(trace_id, 32) && is_hex(span_id, 16) => {
+                Some(TraceContext {
+                    trace_id: trace_id.to_lowercase(),
+                    span_id: span_id.to_lowercase(),
+                    parent_span_id: None,
+                    op: None,
+                }
+                .child(op))
+            }
+            _ => None,
+        }
+    }
+
+    /// The `sentry-trace` header propagating this span to downstream services.
+    pub fn sentry_trace(&self) -> String {
+        format!("{}-{}", self.trace_id, self.span_id)
+    }
+}
+
+// see https://develop.sentry.dev/sdk/event-payloads/span/
+/// A timed operation within a transaction, ex: a database query.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Span {
+    pub trace_id: String,
+    pub span_id: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parent_span_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub op: Option<String>,
+    /// ex: "SELECT * FROM users WHERE id = ?"
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(with = "timestamp")]
+    pub start_timestamp: Timestamp,
+    #[serde(with = "timestamp")]
+    pub timestamp: Timestamp,
+    /// ex: "ok", "internal_error", "not_found"
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub status: Option<String>,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub tags: HashMap<String, String>,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub data: HashMap<String, Value>,
+}
+
+impl Span {
+    /// The span identified by `context`, from `start` to `end`.
+    pub fn new(context: &TraceContext, start: Timestamp, end: Timestamp) -> Span {
+        Span {
+            trace_id: context.trace_id.clone(),
+            span_id: context.span_id.clone(),
+            parent_span_id: context.parent_span_id.clone(),
+            op: context.op.clone(),
+            description: None,
+            start_timestamp: start,
+            timestamp: end,
+            status: None,
+            tags: HashMap::new(),
+            data: HashMap::new(),
+        }
+    }
+}
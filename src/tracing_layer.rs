@@ -0,0 +1,193 @@
+//! Integration with `tracing`, the counterpart of the `log` integration for
+//! applications instrumented with spans.
+
+use std::fmt;
+use std::time::Instant;
+
+use tracing_core::{Event as TracingEvent, Level, Subscriber};
+use tracing_core::field::{Field, Visit};
+use tracing_core::span::{Attributes, Id, Record};
+use tracing_subscriber::layer::{Context, Layer};
+use tracing_subscriber::registry::LookupSpan;
+
+use {configure_scope, Breadcrumb, Sentry};
+
+/// Fields of a span or event, `message` apart.
+#[derive(Default)]
+struct Fields {
+    message: Option<String>,
+    values: Vec<(String, String)>,
+}
+
+impl Visit for Fields {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "message" {
+            self.message = Some(value.to_string());
+        } else {
+            self.values.push((field.name().to_string(), value.to_string()));
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &fmt::Debug) {
+        if field.name() == "message" {
+            self.message = Some(format!("{:?}", value));
+        } else {
+            self.values.push((field.name().to_string(), format!("{:?}", value)));
+        }
+    }
+}
+
+/// Kept in the extensions of each span.
+struct SpanData {
+    fields: Vec<(String, String)>,
+    started: Instant,
+}
+
+/// A `tracing-subscriber` layer reporting to Sentry:
+///
+/// * error events are sent as Sentry events, with their fields as extra, the
+///   fields of the spans they happened in as tags, and the outermost span as
+///   the transaction;
+/// * warn and info events are added as breadcrumbs to the scope of the
+///   current thread;
+/// * closed spans are added as breadcrumbs with their fields and duration.
+///   Spans aren't sent as transactions; OpenTelemetry users get those from
+///   `SentrySpanProcessor` (`otel` feature).
+///
+/// Events and spans of the `sentry` targets, such as `sentry::internal` which
+/// the client logs its own diagnostics under, are ignored.
+pub struct SentryLayer {
+    sentry: Sentry,
+}
+
+impl SentryLayer {
+    pub fn new(sentry: Sentry) -> SentryLayer {
+        sentry.add_integration("tracing");
+        SentryLayer { sentry: sentry }
+    }
+}
+
+fn is_internal(target: &str) -> bool {
+    target == "sentry" || target.starts_with("sentry::")
+}
+
+fn breadcrumb_level(level: &Level) -> &'static str {
+    if *level == Level::ERROR {
+        "error"
+    } else if *level == Level::WARN {
+        "warning"
+    } else if *level == Level::INFO {
+        "info"
+    } else {
+        "debug"
+    }
+}
+
+impl<S> Layer<S> for SentryLayer
+    where S: Subscriber + for<'a> LookupSpan<'a>
+{
+    fn on_new_span(&self, attrs: &Attributes, id: &Id, ctx: Context<S>) {
+        if !self.sentry.is_enabled() {
+            return;
+        }
+        let span = match ctx.span(id) {
+            Some(span) => span,
+            None => return,
+        };
+        let mut fields = Fields::default();
+        attrs.record(&mut fields);
+        span.extensions_mut().insert(SpanData {
+            fields: fields.values,
+            started: Instant::now(),
+        });
+    }
+
+    fn on_record(&self, id: &Id, values: &Record, ctx: Context<S>) {
+        let span = match ctx.span(id) {
+            Some(span) => span,
+            None => return,
+        };
+        let mut fields = Fields::default();
+        values.record(&mut fields);
+        let mut extensions = span.extensions_mut();
+        if let Some(data) = extensions.get_mut::<SpanData>() {
+            data.fields.extend(fields.values);
+        }
+    }
+
+    fn on_event(&self, event: &TracingEvent, ctx: Context<S>) {
+        if !self.sentry.is_enabled() {
+            return;
+        }
+        let metadata = event.metadata();
+        let target = metadata.target();
+        if is_internal(target) {
+            return;
+        }
+        let level = metadata.level();
+        if *level > Level::INFO {
+            return;
+        }
+        let mut fields = Fields::default();
+        event.record(&mut fields);
+        let message = fields.message.unwrap_or_else(|| metadata.name().to_string());
+
+        if *level != Level::ERROR {
+            let mut breadcrumb = Breadcrumb::new(&message);
+            breadcrumb.set_category(Some(target));
+            breadcrumb.set_level(breadcrumb_level(level));
+            for (key, value) in fields.values {
+                breadcrumb.set_data(key, value);
+            }
+            configure_scope(|scope| scope.add_breadcrumb(breadcrumb));
+            return;
+        }
+
+        let mut e = self.sentry.event(target, "error", &message);
+        if let (Some(file), Some(line)) = (metadata.file(), metadata.line()) {
+            e.set_culprit(Some(&format!("{}: {}", file, line)));
+        }
+        if let Some(scope) = ctx.event_scope(event) {
+            for span in scope.from_root() {
+                if e.transaction().is_none() {
+                    e.set_transaction(Some(span.name()));
+                }
+                if let Some(data) = span.extensions().get::<SpanData>() {
+                    for &(ref key, ref value) in &data.fields {
+                        e.push_tag(key.clone(), value.clone());
+                    }
+                }
+            }
+        }
+        for (key, value) in fields.values {
+            e.set_extra(key, value);
+        }
+        self.sentry.log_event(e);
+    }
+
+    fn on_close(&self, id: Id, ctx: Context<S>) {
+        let span = match ctx.span(&id) {
+            Some(span) => span,
+            None => return,
+        };
+        let metadata = span.metadata();
+        if *metadata.level() > Level::INFO || is_internal(metadata.target()) {
+            return;
+        }
+        let extensions = span.extensions();
+        let data = match extensions.get::<SpanData>() {
+            Some(data) => data,
+            None => return,
+        };
+        let elapsed = data.started.elapsed();
+        let mut breadcrumb = Breadcrumb::new(span.name());
+        breadcrumb.set_category(Some(metadata.target()));
+        breadcrumb.set_level(breadcrumb_level(metadata.level()));
+        breadcrumb.set_data("duration_ms".to_string(),
+                            (elapsed.as_secs() * 1000 + elapsed.subsec_nanos() as u64 / 1_000_000).to_string());
+        for &(ref key, ref value) in &data.fields {
+            breadcrumb.set_data(key.clone(), value.clone());
+        }
+        configure_scope(|scope| scope.add_breadcrumb(breadcrumb));
+    }
+}
@@ -0,0 +1,179 @@
+//! Transactions timed in process, with the spans `sentry::trace` records on
+//! the thread running them.
+
+use std::cell::RefCell;
+use std::marker::PhantomData;
+use std::mem;
+use std::thread;
+
+use clock::SharedClock;
+use delivery::EventDelivery;
+use scope;
+use timestamp::Timestamp;
+use trace::{Span, TraceContext};
+use Sentry;
+
+struct Recording {
+    clock: SharedClock,
+    spans: Vec<Span>,
+}
+
+thread_local! {
+    // the spans of the transaction in progress on this thread
+    static RECORDING: RefCell<Option<Recording>> = RefCell::new(None);
+}
+
+/// The span active on the current thread's scope.
+pub fn current_trace() -> Option<TraceContext> {
+    let mut trace = None;
+    scope::try_with_current(|scope| trace = scope.trace.clone());
+    trace
+}
+
+fn set_trace(trace: Option<TraceContext>) {
+    scope::configure_scope(|scope| scope.set_trace(trace));
+}
+
+fn now() -> Option<Timestamp> {
+    RECORDING.with(|recording| recording.borrow().as_ref().map(|recording| recording.clock.now()))
+}
+
+// ends the span, even if the traced closure panicked
+struct SpanGuard {
+    context: TraceContext,
+    parent: TraceContext,
+    start: Timestamp,
+}
+
+impl Drop for SpanGuard {
+    fn drop(&mut self) {
+        set_trace(Some(self.parent.clone()));
+        let _ = RECORDING.try_with(|recording| {
+            if let Some(ref mut recording) = *recording.borrow_mut() {
+                let mut span = Span::new(&self.context, self.start, recording.clock.now());
+                if thread::panicking() {
+                    span.status = Some("internal_error".to_string());
+                }
+                recording.spans.push(span);
+            }
+        });
+    }
+}
+
+/// Runs `f` as a span `op` (ex: "db.query") of the transaction in progress on
+/// the current thread, started with `Sentry::start_transaction`; spans traced
+/// within `f` are its children. Outside of a transaction, `f` is just run.
+///
+/// ```
+/// # fn load_user(id: u32) -> u32 { id }
+/// let user = sentry::trace("db.query", || load_user(42));
+/// ```
+pub fn trace<T, F: FnOnce() -> T>(op: &str, f: F) -> T {
+    let (start, parent) = match (now(), current_trace()) {
+        (Some(start), Some(parent)) => (start, parent),
+        _ => return f(),
+    };
+    let context = parent.child(Some(op));
+    set_trace(Some(context.clone()));
+    let _guard = SpanGuard {
+        context: context,
+        parent: parent,
+        start: start,
+    };
+    f()
+}
+
+/// A transaction in progress on the current thread, returned by
+/// `Sentry::start_transaction`. It is sent, with the spans traced meanwhile,
+/// by `finish` or when dropped, with the status "internal_error" if dropped
+/// while panicking and "ok" if none was set.
+///
+/// It stays on the thread that started it, whose scope and spans it uses:
+///
+/// ```compile_fail
+/// # use sentry::Sentry;
+/// # let sentry = Sentry::disabled();
+/// let transaction = sentry.start_transaction("GET /users", "http.server");
+/// std::thread::spawn(move || transaction.finish());
+/// ```
+pub struct Transaction {
+    sentry: Sentry,
+    name: String,
+    context: TraceContext,
+    start: Timestamp,
+    status: Option<String>,
+    previous_trace: Option<TraceContext>,
+    previous: Option<Recording>,
+    finished: bool,
+    // not `Send`, as the thread-local state above is the starting thread's
+    thread: PhantomData<*const ()>,
+}
+
+impl Transaction {
+    /// Starts the transaction `name` as the span `context`, which becomes the
+    /// active span of the current thread's scope.
+    pub fn start(sentry: &Sentry, name: &str, context: TraceContext, clock: SharedClock) -> Transaction {
+        let previous_trace = current_trace();
+        set_trace(Some(context.clone()));
+        let start = clock.now();
+        let recording = Recording {
+            clock: clock,
+            spans: vec![],
+        };
+        let previous = RECORDING.with(|current| current.borrow_mut().replace(recording));
+        Transaction {
+            sentry: sentry.clone(),
+            name: name.to_string(),
+            context: context,
+            start: start,
+            status: None,
+            previous_trace: previous_trace,
+            previous: previous,
+            finished: false,
+            thread: PhantomData,
+        }
+    }
+
+    /// The root span, e.g. to propagate with its `sentry_trace` header.
+    pub fn context(&self) -> &TraceContext {
+        &self.context
+    }
+
+    /// Sets the outcome, ex: "ok", "internal_error" or "not_found".
+    pub fn set_status(&mut self, status: &str) {
+        self.status = Some(status.to_string());
+    }
+
+    /// Ends the transaction and sends it.
+    pub fn finish(mut self) -> EventDelivery {
+        self.end()
+    }
+
+    fn end(&mut self) -> EventDelivery {
+        self.finished = true;
+        let previous = self.previous.take();
+        let recording = RECORDING.try_with(|current| mem::replace(&mut *current.borrow_mut(), previous))
+            .ok()
+            .and_then(|recording| recording);
+        set_trace(self.previous_trace.take());
+        let (end, spans) = match recording {
+            Some(recording) => (recording.clock.now(), recording.spans),
+            None => (self.start, vec![]),
+        };
+        let status = match self.status.take() {
+            Some(status) => status,
+            None if thread::panicking() => "internal_error".to_string(),
+            None => "ok".to_string(),
+        };
+        let e = self.sentry.transaction(&self.name, &self.context, Some(&status), self.start, end, spans);
+        self.sentry.log_event(e)
+    }
+}
+
+impl Drop for Transaction {
+    fn drop(&mut self) {
+        if !self.finished {
+            self.end();
+        }
+    }
+}
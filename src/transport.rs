@@ -0,0 +1,1425 @@
+use std::collections::VecDeque;
+use std::fmt::{self, Write as FmtWrite};
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, RwLock};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::time::{Duration, Instant, SystemTime};
+
+use auth::AuthHeader;
+use clock::SharedClock;
+use dedupe::Dedupe;
+use delivery::DeliveryStatus;
+use errors::*;
+use internal::{self, Throttle};
+use log::LogLevel;
+use monitor::CheckIn;
+use normalize;
+use resolve::{Resolve, Resolver};
+use sample;
+use session::{SessionAggregates, SessionUpdate};
+use sink::Sink;
+use serde::Serialize;
+use serde_json;
+use spool::Spool;
+use stats;
+use syslog::Syslog;
+use tags;
+use timestamp;
+use trim;
+use validate;
+use {Event, SentryCredential, Settings, MAX_EVENT_SIZE};
+
+#[cfg(feature = "hyper-transport")]
+use std::io;
+#[cfg(feature = "hyper-transport")]
+use tokio_core::net::TcpStream;
+#[cfg(feature = "hyper-transport")]
+use tokio_core::reactor::{Core, Handle, Timeout};
+#[cfg(feature = "hyper-transport")]
+use futures::future::{self, Either, Future, IntoFuture};
+#[cfg(feature = "hyper-transport")]
+use futures::Stream;
+#[cfg(feature = "hyper-transport")]
+use hyper::{self, Client, Method};
+#[cfg(feature = "hyper-transport")]
+use hyper::Uri;
+#[cfg(feature = "hyper-transport")]
+use hyper::client::{Request, Service};
+#[cfg(feature = "hyper-transport")]
+use hyper::header::{Headers, Authorization, Basic, UserAgent};
+#[cfg(feature = "hyper-transport")]
+use hyper_tls::HttpsConnector;
+
+#[cfg(feature = "hyper-transport")]
+use std::str;
+
+#[cfg(not(feature = "hyper-transport"))]
+use std::io::{self, Read, Write};
+#[cfg(not(feature = "hyper-transport"))]
+use std::net::TcpStream;
+#[cfg(all(feature = "native-tls", any(feature = "hyper-transport", not(feature = "rustls-tls"))))]
+use native_tls::TlsConnector;
+#[cfg(all(feature = "rustls-tls", not(feature = "hyper-transport")))]
+use rustls::{ClientConfig, ClientConnection, RootCertStore, StreamOwned};
+#[cfg(all(feature = "rustls-tls", not(feature = "hyper-transport")))]
+use rustls::pki_types::ServerName;
+#[cfg(all(feature = "rustls-tls", not(feature = "hyper-transport")))]
+use webpki_roots;
+#[cfg(all(feature = "rustls-tls", not(feature = "hyper-transport")))]
+use std::convert::TryFrom;
+#[cfg(not(feature = "hyper-transport"))]
+use base64;
+use url::Url;
+
+/// Where events are posted without a tunnel: `{host}` and `{project}` are
+/// the DSN's host and project id, `{endpoint}` is "store" for events sent as
+/// JSON, "envelope" for everything else.
+pub const DEFAULT_ENDPOINT_TEMPLATE: &'static str = "https://{host}/api/{project}/{endpoint}/";
+
+/// How long connecting to Sentry may take unless `Settings::connect_timeout`
+/// says otherwise.
+pub const DEFAULT_CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How long a request may wait on Sentry unless `Settings::request_timeout`
+/// says otherwise: for each read or write of the blocking client, for the
+/// whole exchange with hyper.
+pub const DEFAULT_REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Which destinations an event still has to reach, carried by the event
+/// between attempts so a retry skips the destinations that accepted it.
+/// Events compare equal whatever their retry state.
+#[derive(Debug, Clone, Default)]
+pub struct Retry {
+    pending: Option<Vec<String>>, // destination ids, all destinations while `None`
+    accepted: bool, // by any destination, in any attempt
+}
+
+impl PartialEq for Retry {
+    fn eq(&self, _other: &Retry) -> bool {
+        true
+    }
+}
+
+/// What the worker hands to the transport.
+#[derive(Debug)]
+pub enum Message {
+    Event(Event),
+    Session(SessionUpdate),
+    Sessions(SessionAggregates),
+    CheckIn(CheckIn),
+}
+
+/// Everything a transport needs to deliver one event to the store endpoint.
+pub struct StoreRequest<'a> {
+    pub url: &'a str,
+    pub auth: &'a str, // X-Sentry-Auth header value
+    pub basic: Option<(&'a str, &'a str)>, // HTTP Basic username and password
+    pub user_agent: &'a str,
+    pub headers: &'a [(String, String)], // extra headers, e.g. for a reverse proxy
+    pub content_type: &'a str,
+    pub addrs: &'a [SocketAddr], // the URL host, resolved
+    pub body: &'a [u8],
+    pub connect_timeout: Duration,
+    pub timeout: Duration,
+}
+
+/// What the server answered to a `StoreRequest`.
+pub struct Response {
+    pub status: u16,
+    pub retry_after: Option<u64>, // seconds, from the Retry-After header
+    pub date: Option<String>, // the Date header, the server's time
+    pub body: String,
+}
+
+/// Buffers owned by the worker and reused for every event it sends, so an
+/// error storm doesn't allocate fresh payloads and header strings per event.
+#[derive(Debug, Default)]
+pub struct SendBuffers {
+    pub body: Vec<u8>,
+    pub envelope: Vec<u8>, // the body wrapped for a tunnel or sink
+    pub auth: String,
+    pub url: String,
+}
+
+/// How the client identifies itself on every request.
+struct RequestOptions {
+    client: String, // sentry_client in X-Sentry-Auth
+    user_agent: String,
+    headers: Vec<(String, String)>,
+    tunnel: Option<String>,
+    endpoint_template: String,
+    basic_auth: bool,
+    resolver: Resolver,
+    connect_timeout: Duration,
+    timeout: Duration,
+}
+
+/// Delivery status of a client, for readiness probes.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Health {
+    /// Outcome of the most recent send, `None` until one was attempted.
+    pub last_result: Option<::std::result::Result<(), String>>,
+    /// When a destination last accepted an event.
+    pub last_success: Option<SystemTime>,
+    /// How much longer the longest rate limit lasts, if any destination is
+    /// backing off.
+    pub rate_limited_for: Option<Duration>,
+    /// Undelivered events held for the next flush.
+    pub pending: usize,
+    /// Spooled events evicted to respect the spool limits.
+    pub spool_evicted: usize,
+    /// Destinations whose circuit breaker is open.
+    pub open_circuits: usize,
+}
+
+impl Health {
+    /// Whether the last send succeeded and nothing is backing off.
+    pub fn is_healthy(&self) -> bool {
+        self.rate_limited_for.is_none() && self.open_circuits == 0 &&
+        match self.last_result {
+            Some(Err(_)) => false,
+            _ => true,
+        }
+    }
+}
+
+/// How long to back off after a 429 that doesn't say.
+const DEFAULT_RETRY_AFTER: u64 = 60;
+
+/// Undelivered events held for the next flush; older ones are dropped first.
+const MAX_PENDING_EVENTS: usize = 100;
+
+/// How often an open circuit lets an event through by default, in seconds.
+const DEFAULT_PROBE_INTERVAL: u64 = 30;
+
+/// Consecutive failures of a host before a destination moves on to the next.
+const FAILOVER_AFTER: usize = 3;
+
+/// Smallest difference with the server's clock, in seconds, corrected for;
+/// below it, it's latency and the one second resolution of `Date`.
+const MIN_CLOCK_SKEW: i64 = 60;
+
+/// A Sentry project events are delivered to, rate limited independently of
+/// any other destination.
+pub struct Destination {
+    credentials: Vec<SentryCredential>, // the primary host, then its fallbacks
+    current: AtomicUsize,
+    failures: AtomicUsize, // of the current host, in a row
+    consecutive_failures: AtomicUsize, // of any host
+    circuit_open_until: Mutex<Option<Instant>>, // set while tripped, even once a probe is due
+    rate_limited_until: Mutex<Option<Instant>>,
+}
+
+impl Destination {
+    pub fn new(credential: SentryCredential) -> Destination {
+        Destination::with_fallbacks(vec![credential])
+    }
+
+    /// A destination delivering to the first of `credentials` and moving to
+    /// the next after `FAILOVER_AFTER` failures in a row, from the last back
+    /// to the first.
+    pub fn with_fallbacks(credentials: Vec<SentryCredential>) -> Destination {
+        assert!(!credentials.is_empty(), "a destination needs a credential");
+        Destination {
+            credentials: credentials,
+            current: AtomicUsize::new(0),
+            failures: AtomicUsize::new(0),
+            consecutive_failures: AtomicUsize::new(0),
+            circuit_open_until: Mutex::new(None),
+            rate_limited_until: Mutex::new(None),
+        }
+    }
+
+    /// Identifies the destination across attempts, whichever host it fails
+    /// over to.
+    fn id(&self) -> String {
+        let primary = &self.credentials[0];
+        format!("{}@{}/{}", primary.key, primary.host, primary.project_id)
+    }
+
+    /// The credential of the host currently delivered to.
+    pub fn credential(&self) -> &SentryCredential {
+        &self.credentials[self.current.load(Ordering::SeqCst)]
+    }
+
+    fn succeeded(&self) {
+        self.failures.store(0, Ordering::SeqCst);
+        self.consecutive_failures.store(0, Ordering::SeqCst);
+        *lock(&self.circuit_open_until) = None;
+    }
+
+    /// Counts a failure of the current host (unreachable or a server error),
+    /// returning the credential failed over to, if any.
+    fn failed(&self) -> Option<&SentryCredential> {
+        self.consecutive_failures.fetch_add(1, Ordering::SeqCst);
+        if self.credentials.len() < 2 || self.failures.fetch_add(1, Ordering::SeqCst) + 1 < FAILOVER_AFTER {
+            return None;
+        }
+        self.failures.store(0, Ordering::SeqCst);
+        let next = (self.current.load(Ordering::SeqCst) + 1) % self.credentials.len();
+        self.current.store(next, Ordering::SeqCst);
+        // a rate limit is the old host's
+        *lock(&self.rate_limited_until) = None;
+        Some(&self.credentials[next])
+    }
+
+    /// Opens the circuit until `until` once `threshold` failures happened in a
+    /// row, which a failed probe does again. Returns whether it was closed.
+    fn trip(&self, threshold: usize, until: Instant) -> bool {
+        if self.consecutive_failures.load(Ordering::SeqCst) < threshold {
+            return false;
+        }
+        let mut open_until = lock(&self.circuit_open_until);
+        let was_closed = open_until.is_none();
+        *open_until = Some(until);
+        was_closed
+    }
+
+    /// How long until the open circuit lets a probe through, `None` if it is
+    /// closed or a probe is due.
+    fn circuit_remaining(&self, now: Instant) -> Option<Duration> {
+        match *lock(&self.circuit_open_until) {
+            Some(until) if now < until => Some(until - now),
+            _ => None,
+        }
+    }
+
+    /// Whether the server asked us to hold off sending to this destination.
+    pub fn is_rate_limited(&self, now: Instant) -> bool {
+        self.rate_limit_remaining(now).is_some()
+    }
+
+    fn rate_limit_remaining(&self, now: Instant) -> Option<Duration> {
+        match *lock(&self.rate_limited_until) {
+            Some(until) if now < until => Some(until - now),
+            _ => None,
+        }
+    }
+
+    fn rate_limit_for(&self, seconds: u64, now: Instant) {
+        *lock(&self.rate_limited_until) = Some(now + Duration::from_secs(seconds));
+    }
+
+    /// Posts the serialized `buffers.body`, an item of type `item_type`
+    /// ("event" or "transaction"), sent at `sent_at` by the client's clock.
+    fn post(&self,
+            event_id: Option<&str>,
+            item_type: &str,
+            sent_at: &str,
+            options: &RequestOptions,
+            buffers: &mut SendBuffers)
+            -> Result<Response> {
+        let credential = self.credential();
+        let SendBuffers { ref body, ref mut envelope, ref mut auth, ref mut url } = *buffers;
+
+        auth.clear();
+        write!(auth, "{}", AuthHeader::new(&options.client, &credential.key, &credential.secret))
+            .map_err(|e| e.to_string())?;
+
+        url.clear();
+        let (body, content_type) = match options.tunnel {
+            Some(ref tunnel) => {
+                url.push_str(tunnel);
+                write_envelope(envelope, event_id, item_type, sent_at, credential, body)?;
+                (&envelope[..], "application/x-sentry-envelope")
+            }
+            // the store endpoint only takes events
+            None if item_type != "event" => {
+                write_endpoint(url, &options.endpoint_template, credential, "envelope");
+                write_envelope(envelope, event_id, item_type, sent_at, credential, body)?;
+                (&envelope[..], "application/x-sentry-envelope")
+            }
+            None => {
+                write_endpoint(url, &options.endpoint_template, credential, "store");
+                (&body[..], "application/json")
+            }
+        };
+
+        let parsed = Url::parse(url).map_err(|e| e.to_string())?;
+        let host = parsed.host_str().ok_or("Sentry URL has no host")?;
+        let addrs = options.resolver.resolve(host, parsed.port_or_known_default().unwrap_or(443))?;
+
+        send(StoreRequest {
+            url: url,
+            addrs: &addrs,
+            auth: auth,
+            basic: if options.basic_auth {
+                Some((&credential.key, &credential.secret))
+            } else {
+                None
+            },
+            user_agent: &options.user_agent,
+            headers: &options.headers,
+            content_type: content_type,
+            body: body,
+            connect_timeout: options.connect_timeout,
+            timeout: options.timeout,
+        })
+    }
+}
+
+impl Destination {
+    /// Publishes the serialized `buffers.body` to `sink` as an envelope.
+    fn publish(&self,
+               event_id: Option<&str>,
+               item_type: &str,
+               sent_at: &str,
+               sink: &Sink,
+               buffers: &mut SendBuffers)
+               -> Result<Response> {
+        let SendBuffers { ref body, ref mut envelope, .. } = *buffers;
+        write_envelope(envelope, event_id, item_type, sent_at, self.credential(), body)?;
+        sink.publish(envelope)?;
+        Ok(Response {
+            status: 200,
+            retry_after: None,
+            date: None,
+            body: String::new(),
+        })
+    }
+}
+
+/// Expands the placeholders of `template` into `url`, leaving unknown ones,
+/// ex: `{org}`, as they are.
+fn write_endpoint(url: &mut String, template: &str, credential: &SentryCredential, endpoint: &str) {
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        url.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let end = rest.find('}').map_or(rest.len(), |end| end + 1);
+        match &rest[..end] {
+            "{host}" => url.push_str(&credential.host),
+            "{project}" => url.push_str(&credential.project_id),
+            "{endpoint}" => url.push_str(endpoint),
+            placeholder => url.push_str(placeholder),
+        }
+        rest = &rest[end..];
+    }
+    url.push_str(rest);
+}
+
+/// Wraps an item in an envelope whose header names the DSN, so a tunnel can
+/// forward it to the right project, and tells when it was sent, so Sentry can
+/// correct the timestamps of a client whose clock is off.
+fn write_envelope(envelope: &mut Vec<u8>,
+                  event_id: Option<&str>,
+                  item_type: &str,
+                  sent_at: &str,
+                  credential: &SentryCredential,
+                  event: &[u8])
+                  -> Result<()> {
+    #[derive(Serialize)]
+    struct EnvelopeHeader<'a> {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        event_id: Option<&'a str>,
+        dsn: String,
+        sent_at: &'a str,
+    }
+    #[derive(Serialize)]
+    struct ItemHeader<'a> {
+        #[serde(rename = "type")]
+        ty: &'a str,
+        length: usize,
+    }
+
+    envelope.clear();
+    let header = EnvelopeHeader {
+        event_id: event_id,
+        dsn: format!("https://{}@{}/{}", credential.key, credential.host, credential.project_id),
+        sent_at: sent_at,
+    };
+    serde_json::to_writer(&mut *envelope, &header)?;
+    envelope.push(b'\n');
+    serde_json::to_writer(&mut *envelope, &ItemHeader { ty: item_type, length: event.len() })?;
+    envelope.push(b'\n');
+    envelope.extend_from_slice(event);
+    Ok(())
+}
+
+/// Sends events from loggers under `prefix` to their own destination.
+struct Route {
+    prefix: String,
+    destinations: Arc<Vec<Destination>>,
+}
+
+impl Route {
+    fn new(pattern: String, credential: SentryCredential) -> Route {
+        Route {
+            prefix: sample::prefix(&pattern).to_string(),
+            destinations: Arc::new(vec![Destination::new(credential)]),
+        }
+    }
+
+    /// Matches the logger named by the prefix and its dotted descendants.
+    fn matches(&self, logger: &str) -> bool {
+        sample::is_under(logger, &self.prefix)
+    }
+}
+
+/// Delivers events to every configured destination, or to the destination of
+/// the most specific route matching the event's logger.
+pub struct Transport {
+    destinations: RwLock<Arc<Vec<Destination>>>,
+    routes: Vec<Route>,
+    buffers: Mutex<SendBuffers>,
+    health: Mutex<Health>,
+    pending: Mutex<VecDeque<Event>>,
+    queued: AtomicUsize,
+    throttle: Throttle,
+    failing_since: Mutex<Option<Instant>>,
+    retry_at: Mutex<Option<Instant>>, // next flush of held events
+    flush_interval: Option<Duration>,
+    circuit_breaker: Option<(usize, Duration)>, // failures in a row, probe interval
+    syslog: Option<(Duration, Syslog)>,
+    spool: Option<Spool>,
+    dedupe: Option<Dedupe>,
+    retry: bool,
+    sdk_error_handler: RwLock<Option<Box<Fn(&Error) + Send + Sync>>>,
+    sink: RwLock<Option<Box<Sink>>>,
+    options: RequestOptions,
+    enabled: AtomicBool,
+    debug: bool,
+    epoch_timestamps: bool,
+    correct_clock_skew: bool,
+    clock_skew: Mutex<i64>, // seconds the local clock is behind the server's
+    pub clock: SharedClock,
+}
+
+impl Transport {
+    pub fn new(destinations: Vec<Destination>, settings: &Settings) -> Transport {
+        let client = settings.client_name
+            .clone()
+            .or_else(|| settings.sdk.as_ref().map(|&(ref name, ref version)| format!("{}/{}", name, version)))
+            .unwrap_or_else(|| concat!("rust-sentry/", env!("CARGO_PKG_VERSION")).to_string());
+        let enabled = !destinations.is_empty() || !settings.routes.is_empty();
+        let clock = SharedClock::new();
+        Transport {
+            destinations: RwLock::new(Arc::new(destinations)),
+            routes: settings.routes
+                .iter()
+                .map(|&(ref pattern, ref credential)| Route::new(pattern.clone(), credential.clone()))
+                .collect(),
+            buffers: Mutex::new(SendBuffers::default()),
+            health: Mutex::new(Health::default()),
+            pending: Mutex::new(VecDeque::new()),
+            queued: AtomicUsize::new(0),
+            throttle: Throttle::new(internal::WINDOW),
+            failing_since: Mutex::new(None),
+            retry_at: Mutex::new(None),
+            flush_interval: settings.flush_interval,
+            circuit_breaker: settings.circuit_breaker_after.map(|threshold| {
+                (threshold, settings.circuit_probe_interval.unwrap_or(Duration::from_secs(DEFAULT_PROBE_INTERVAL)))
+            }),
+            syslog: settings.syslog_fallback_after.map(|after| {
+                (after, Syslog::new(settings.syslog_path.as_ref().map(|path| path.as_path()), &settings.server_name))
+            }),
+            spool: settings.spool_dir.clone().map(|dir| spool(dir, settings)),
+            dedupe: settings.dedupe_window.map(|window| Dedupe::new(window, clock.clone())),
+            retry: settings.flush_interval.is_some() || settings.spool_dir.is_some(),
+            sdk_error_handler: RwLock::new(None),
+            sink: RwLock::new(None),
+            options: RequestOptions {
+                client: client.clone(),
+                user_agent: settings.user_agent.clone().unwrap_or(client),
+                headers: settings.extra_headers
+                    .iter()
+                    .filter(|&&(ref name, ref value)| {
+                        // a line break would let a header smuggle others into the request
+                        let valid = !name.contains(|c| c == '\r' || c == '\n' || c == ':') &&
+                                    !value.contains(|c| c == '\r' || c == '\n');
+                        if !valid {
+                            warn!(target: internal::TARGET, "Ignoring invalid Sentry request header {:?}", name);
+                        }
+                        valid
+                    })
+                    .cloned()
+                    .collect(),
+                tunnel: settings.tunnel.clone(),
+                endpoint_template: settings.endpoint_template
+                    .clone()
+                    .unwrap_or_else(|| DEFAULT_ENDPOINT_TEMPLATE.to_string()),
+                basic_auth: settings.basic_auth,
+                resolver: Resolver::new(settings.dns_cache_ttl),
+                connect_timeout: settings.connect_timeout.unwrap_or(DEFAULT_CONNECT_TIMEOUT),
+                timeout: settings.request_timeout.unwrap_or(DEFAULT_REQUEST_TIMEOUT),
+            },
+            enabled: AtomicBool::new(enabled),
+            debug: settings.debug,
+            epoch_timestamps: settings.epoch_timestamps,
+            correct_clock_skew: settings.correct_clock_skew,
+            clock_skew: Mutex::new(0),
+            clock: clock,
+        }
+    }
+
+    /// Logs `args` under the `sentry::internal` target when debug mode is on.
+    pub fn debug_log(&self, args: fmt::Arguments) {
+        if self.debug {
+            info!(target: internal::TARGET, "{}", args);
+        }
+    }
+
+    /// Like `debug_log`, for failures that repeat while a destination is
+    /// down, so they are logged once a minute at most.
+    fn debug_failure(&self, args: fmt::Arguments) {
+        if self.debug {
+            internal::log(&self.throttle, LogLevel::Info, &args.to_string());
+        }
+    }
+
+    pub fn set_sdk_error_handler(&self, handler: Box<Fn(&Error) + Send + Sync>) {
+        match self.sdk_error_handler.write() {
+            Ok(mut guard) => *guard = Some(handler),
+            Err(poisoned) => *poisoned.into_inner() = Some(handler),
+        }
+    }
+
+    /// Reports a problem of the client itself, as opposed to delivery
+    /// failures, which `health` covers.
+    pub fn sdk_error(&self, e: &Error) {
+        internal::log(&self.throttle, LogLevel::Warn, &e.to_string());
+        let guard = match self.sdk_error_handler.read() {
+            Ok(guard) => guard,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        if let Some(ref handler) = *guard {
+            handler(e);
+        }
+    }
+
+    pub fn set_resolver(&self, resolver: Box<Resolve>) {
+        self.options.resolver.set_custom(resolver);
+    }
+
+    pub fn set_sink(&self, sink: Box<Sink>) {
+        match self.sink.write() {
+            Ok(mut guard) => *guard = Some(sink),
+            Err(poisoned) => *poisoned.into_inner() = Some(sink),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled.load(Ordering::Relaxed)
+    }
+
+    pub fn set_enabled(&self, enabled: bool) {
+        self.enabled.store(enabled, Ordering::Relaxed);
+    }
+
+    /// The tunnel and endpoint template events are posted to.
+    pub fn endpoint(&self) -> (Option<&str>, &str) {
+        (self.options.tunnel.as_ref().map(|tunnel| &tunnel[..]), &self.options.endpoint_template)
+    }
+
+    pub fn destinations(&self) -> Arc<Vec<Destination>> {
+        match self.destinations.read() {
+            Ok(guard) => guard.clone(),
+            Err(poisoned) => poisoned.into_inner().clone(),
+        }
+    }
+
+    /// The last send outcome and the current rate limits across all
+    /// destinations, routed ones included.
+    pub fn health(&self) -> Health {
+        let mut health = lock(&self.health).clone();
+        let now = self.clock.instant();
+        health.rate_limited_for = self.rate_limit_remaining(now);
+        health.open_circuits = self.open_circuits(now).0;
+        health.pending = lock(&self.pending).len();
+        health.spool_evicted = self.spool.as_ref().map_or(0, Spool::evicted);
+        health
+    }
+
+    /// The longest rate limit of any destination.
+    fn rate_limit_remaining(&self, now: Instant) -> Option<Duration> {
+        let destinations = self.destinations();
+        destinations.iter()
+            .chain(self.routes.iter().flat_map(|route| route.destinations.iter()))
+            .filter_map(|destination| destination.rate_limit_remaining(now))
+            .max()
+    }
+
+    /// The open circuits of all destinations, and how long until the longest
+    /// lets a probe through.
+    fn open_circuits(&self, now: Instant) -> (usize, Option<Duration>) {
+        let destinations = self.destinations();
+        let remaining: Vec<Duration> = destinations.iter()
+            .chain(self.routes.iter().flat_map(|route| route.destinations.iter()))
+            .filter_map(|destination| destination.circuit_remaining(now))
+            .collect();
+        (remaining.len(), remaining.into_iter().max())
+    }
+
+    /// How long until destinations stop rate limiting, open circuits let a
+    /// probe through and held events are retried, `None` if none is pending.
+    pub fn backoff_remaining(&self) -> Option<Duration> {
+        let now = self.clock.instant();
+        let retry = match *lock(&self.retry_at) {
+            Some(at) if now < at && !lock(&self.pending).is_empty() => Some(at - now),
+            _ => None,
+        };
+        self.rate_limit_remaining(now).into_iter().chain(self.open_circuits(now).1).chain(retry).max()
+    }
+
+    /// Replaces the default destinations. Events already queued are sent to
+    /// the new ones; routed loggers are unaffected.
+    pub fn set_credentials(&self, credentials: Vec<SentryCredential>) {
+        let destinations = Arc::new(credentials.into_iter().map(Destination::new).collect());
+        match self.destinations.write() {
+            Ok(mut guard) => *guard = destinations,
+            Err(poisoned) => *poisoned.into_inner() = destinations,
+        }
+    }
+
+    pub fn destinations_for(&self, logger: &str) -> Arc<Vec<Destination>> {
+        let route = self.routes
+            .iter()
+            .filter(|route| route.matches(logger))
+            .max_by_key(|route| route.prefix.len());
+        match route {
+            Some(route) => route.destinations.clone(),
+            None => self.destinations(),
+        }
+    }
+
+    /// Serializes `e` once and posts it to each destination that isn't
+    /// currently rate limited, returning how many accepted it. Fails if any
+    /// destination failed.
+    ///
+    /// While the transport is disabled, events are discarded unsent.
+    pub fn post(&self, e: &mut Event) -> Result<usize> {
+        e.retry = Retry::default();
+        self.post_pending(e)
+    }
+
+    /// Like `post`, but on a retry of `e` only posts to the destinations that
+    /// failed it with a network error, 429 or 5xx; those rejecting it
+    /// otherwise, e.g. with 400 or 413, would only do so again.
+    fn post_pending(&self, e: &mut Event) -> Result<usize> {
+        if !self.is_enabled() {
+            self.debug_log(format_args!("reporting disabled, discarding {} event", e.level));
+            stats::dropped("disabled");
+            return Ok(0);
+        }
+        if self.debug {
+            let violations = validate::violations(e);
+            if !violations.is_empty() {
+                self.sdk_error(&ErrorKind::InvalidEvent(violations.join("; ")).into());
+            }
+        }
+        for key in tags::normalize(&mut e.tags) {
+            self.debug_log(format_args!("truncated or stripped line breaks from tag {:?}", key));
+        }
+        normalize::event(e);
+        let clock_skew = *lock(&self.clock_skew);
+        let sent_at = self.sent_at(clock_skew);
+        let mut guard = lock(&self.buffers);
+        let buffers = &mut *guard;
+        if let Err(err) = trim::serialize_within(e, MAX_EVENT_SIZE, self.epoch_timestamps, clock_skew, &mut buffers.body) {
+            let err = Error::from(ErrorKind::Unserializable(err.to_string()));
+            self.sdk_error(&err);
+            return Err(err);
+        }
+        trace!(target: internal::TARGET, "Sentry request: {}", String::from_utf8_lossy(&buffers.body));
+
+        let sink = match self.sink.read() {
+            Ok(guard) => guard,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        let mut failure = None;
+        let mut attempted = false;
+        let mut delivered = 0;
+        let mut pending = vec![];
+        for destination in self.destinations_for(&e.logger).iter() {
+            let id = destination.id();
+            if e.retry.pending.as_ref().map_or(false, |pending| !pending.contains(&id)) {
+                continue;
+            }
+            let credential = destination.credential();
+            let project_id = &credential.project_id;
+            if destination.is_rate_limited(self.clock.instant()) {
+                self.debug_failure(format_args!("project {} is rate limited, skipping event", project_id));
+                pending.push(id);
+                continue;
+            }
+            if destination.circuit_remaining(self.clock.instant()).is_some() {
+                self.debug_failure(format_args!("circuit to project {} is open, skipping event", project_id));
+                pending.push(id);
+                continue;
+            }
+            self.debug_log(format_args!("sending {} bytes to project {} at {}",
+                                        buffers.body.len(),
+                                        project_id,
+                                        credential.host));
+            attempted = true;
+            let start = Instant::now();
+            let item_type = if e.is_transaction() { "transaction" } else { "event" };
+            let result = match *sink {
+                Some(ref sink) => destination.publish(Some(&e.event_id), item_type, &sent_at, &**sink, buffers),
+                None => destination.post(Some(&e.event_id), item_type, &sent_at, &self.options, buffers),
+            };
+            stats::send_latency(start.elapsed());
+            if let Some(date) = result.as_ref().ok().and_then(|response| response.date.as_ref()) {
+                self.observe_server_date(date);
+            }
+            match result {
+                Ok(ref response) => {
+                    self.debug_log(format_args!("project {} responded with {}", project_id, response.status))
+                }
+                Err(ref e) => self.debug_failure(format_args!("sending to project {} failed: {}", project_id, e)),
+            }
+            match result {
+                Ok(ref response) if response.status == 429 => {
+                    let retry_after = response.retry_after.unwrap_or(DEFAULT_RETRY_AFTER);
+                    destination.rate_limit_for(retry_after, self.clock.instant());
+                    self.debug_log(format_args!("project {} is rate limited for {}s", project_id, retry_after));
+                    stats::failed();
+                    pending.push(id);
+                    failure = Some(Error::from(format!("Sentry project {} is rate limited", project_id)));
+                }
+                Ok(ref response) if response.status >= 400 => {
+                    stats::failed();
+                    if response.status >= 500 {
+                        self.record_failure(destination);
+                        pending.push(id);
+                    }
+                    failure = Some(Error::from(format!("Sentry responded with {}: {}",
+                                                       response.status,
+                                                       response.body)));
+                }
+                Ok(response) => {
+                    trace!(target: internal::TARGET, "Sentry response: {}", response.body);
+                    stats::sent();
+                    destination.succeeded();
+                    delivered += 1;
+                }
+                Err(e) => {
+                    stats::failed();
+                    self.record_failure(destination);
+                    pending.push(id);
+                    failure = Some(e)
+                }
+            }
+        }
+        e.retry.pending = Some(pending);
+        e.retry.accepted |= delivered > 0;
+        if attempted {
+            let mut failing_since = lock(&self.failing_since);
+            *failing_since = match failure {
+                Some(_) => Some(failing_since.unwrap_or_else(|| self.clock.instant())),
+                None => None,
+            };
+            let mut health = lock(&self.health);
+            health.last_result = Some(match failure {
+                Some(ref e) => Err(e.to_string()),
+                None => Ok(()),
+            });
+            if delivered > 0 {
+                health.last_success = Some(SystemTime::from(self.clock.now()));
+            }
+        }
+        match failure {
+            Some(e) => Err(e),
+            None => Ok(delivered),
+        }
+    }
+
+    /// The time by the client's clock, corrected by `clock_skew` like the
+    /// event timestamps, in RFC 3339 format.
+    fn sent_at(&self, clock_skew: i64) -> String {
+        format!("{}Z", timestamp::format(&timestamp::shift(&self.clock.now(), clock_skew)))
+    }
+
+    /// Compares the `Date` of a response with the local clock, with
+    /// `Settings::correct_clock_skew`, to shift the timestamps of the events
+    /// sent next by the difference.
+    pub fn observe_server_date(&self, date: &str) {
+        if !self.correct_clock_skew {
+            return;
+        }
+        let server_time = match timestamp::parse_http_date(date) {
+            Some(server_time) => server_time,
+            None => return,
+        };
+        let skew = (timestamp::epoch(&server_time) - timestamp::epoch(&self.clock.now())).round() as i64;
+        let skew = if skew.abs() < MIN_CLOCK_SKEW { 0 } else { skew };
+        let mut clock_skew = lock(&self.clock_skew);
+        if *clock_skew != skew {
+            self.debug_log(format_args!("local clock is {}s behind Sentry's, correcting timestamps", skew));
+            *clock_skew = skew;
+        }
+    }
+
+    /// Fails over to the next host of `destination` or opens its circuit, once
+    /// it failed often enough in a row.
+    fn record_failure(&self, destination: &Destination) {
+        if let Some(next) = destination.failed() {
+            self.sdk_error(&format!("Sentry host failing repeatedly, failing over to {}", next.host).into());
+        }
+        if let Some((threshold, interval)) = self.circuit_breaker {
+            if destination.trip(threshold, self.clock.instant() + interval) {
+                self.sdk_error(&format!("Sentry project {} failed {} times in a row, pausing sends for {:?}",
+                                        destination.credential().project_id,
+                                        threshold,
+                                        interval)
+                    .into());
+            }
+        }
+    }
+
+    /// Counts an event handed to the worker, for the queue depth metric.
+    pub fn enqueued(&self) {
+        stats::queue_depth(self.queued.fetch_add(1, Ordering::SeqCst) + 1);
+    }
+
+    /// Counts a message handled by the worker, or that didn't fit its queue.
+    pub fn dequeued(&self) {
+        let queued = self.queued.load(Ordering::SeqCst);
+        if queued > 0 {
+            stats::queue_depth(self.queued.fetch_sub(1, Ordering::SeqCst) - 1);
+        }
+    }
+
+    /// Sends a message of the worker.
+    pub fn handle(&self, message: Message) {
+        match message {
+            Message::Event(e) => self.send(e),
+            Message::Session(update) => self.send_item("session", &update),
+            Message::Sessions(aggregates) => self.send_item("sessions", &aggregates),
+            Message::CheckIn(check_in) => self.send_item("check_in", &check_in),
+        }
+    }
+
+    /// Sends `e` unless it repeats an event sent within the dedupe window,
+    /// along with the summaries of repeats whose window ended.
+    pub fn send(&self, e: Event) {
+        self.dequeued();
+        if let Some(ref dedupe) = self.dedupe {
+            self.send_summaries(dedupe);
+            // a transaction repeating the name of a recent one is no duplicate
+            if !e.is_transaction() && !dedupe.admit(&e) {
+                self.debug_log(format_args!("suppressing duplicate {} event from {}", e.level, e.logger));
+                stats::dropped("duplicate");
+                e.delivery.settle(DeliveryStatus::Dropped("duplicate"));
+                return;
+            }
+        }
+        self.deliver(e);
+    }
+
+    /// Posts a session update, the aggregates of request sessions or a job's
+    /// check-in to the default destinations that aren't backing off, once: a
+    /// lost update only skews the release's health statistics, or a monitor's
+    /// timeline.
+    fn send_item<T: Serialize>(&self, ty: &'static str, update: &T) {
+        self.dequeued();
+        if !self.is_enabled() {
+            return;
+        }
+        let mut guard = lock(&self.buffers);
+        let buffers = &mut *guard;
+        buffers.body.clear();
+        if let Err(err) = serde_json::to_writer(&mut buffers.body, update) {
+            self.sdk_error(&ErrorKind::Unserializable(err.to_string()).into());
+            return;
+        }
+        let sink = match self.sink.read() {
+            Ok(guard) => guard,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        let now = self.clock.instant();
+        let sent_at = self.sent_at(*lock(&self.clock_skew));
+        for destination in self.destinations().iter() {
+            if destination.is_rate_limited(now) || destination.circuit_remaining(now).is_some() {
+                continue;
+            }
+            self.debug_log(format_args!("sending {} item", ty));
+            let result = match *sink {
+                Some(ref sink) => destination.publish(None, ty, &sent_at, &**sink, buffers),
+                None => destination.post(None, ty, &sent_at, &self.options, buffers),
+            };
+            match result {
+                Ok(ref response) if response.status < 400 => {}
+                Ok(response) => self.debug_failure(format_args!("{} item refused with {}", ty, response.status)),
+                Err(e) => self.debug_failure(format_args!("sending {} item failed: {}", ty, e)),
+            }
+        }
+    }
+
+    fn send_summaries(&self, dedupe: &Dedupe) {
+        for summary in dedupe.summaries() {
+            self.debug_log(format_args!("reporting repeats of {} event from {}", summary.level, summary.logger));
+            self.deliver(summary);
+        }
+    }
+
+    /// Posts `e`, holding it for `flush_pending` if it couldn't be delivered
+    /// and a flush interval or spool is configured. Held events are written to
+    /// the spool until delivered.
+    ///
+    /// Destinations that already accepted a held event drop the retry, since
+    /// Sentry deduplicates by event id.
+    fn deliver(&self, mut e: Event) {
+        if !self.should_retry(&mut e, true) {
+            return;
+        }
+        if let Some(ref spool) = self.spool {
+            if let Err(err) = spool.store(&e) {
+                self.sdk_error(&format!("Failed to spool Sentry event {}: {}", e.event_id, err).into());
+            }
+        }
+        let mut pending = lock(&self.pending);
+        if pending.len() >= MAX_PENDING_EVENTS {
+            self.sdk_error(&"Sentry retry queue is full, dropping the oldest event".into());
+            if let Some(dropped) = pending.pop_front() {
+                stats::dropped("queue_full");
+                dropped.delivery.settle(DeliveryStatus::Dropped("queue_full"));
+                self.unspool(&dropped);
+            }
+        }
+        self.debug_log(format_args!("holding {} event for retry", e.level));
+        self.back_off();
+        pending.push_back(e);
+        stats::pending(pending.len());
+    }
+
+    /// Writes `e` to syslog if destinations have been failing for longer than
+    /// `Settings::syslog_fallback_after`.
+    fn fall_back(&self, e: &Event) {
+        let (after, syslog) = match self.syslog {
+            Some((after, ref syslog)) => (after, syslog),
+            None => return,
+        };
+        let failing_for = lock(&self.failing_since).map(|since| self.clock.instant().duration_since(since));
+        if e.is_transaction() || failing_for.map_or(true, |failing_for| failing_for < after) {
+            return;
+        }
+        self.debug_log(format_args!("Sentry failing for {:?}, writing {} event to syslog", failing_for, e.level));
+        if let Err(err) = syslog.send(e) {
+            self.sdk_error(&format!("Failed to write Sentry event {} to syslog: {}", e.event_id, err).into());
+        }
+    }
+
+    /// Reads back the events a previous run left in the spool, so they can
+    /// be sent again with their original ids and timestamps.
+    pub fn replay_spool(&self) -> Vec<Event> {
+        let spool = match self.spool {
+            Some(ref spool) => spool,
+            None => return vec![],
+        };
+        match spool.load() {
+            Ok(events) => {
+                self.debug_log(format_args!("replaying {} spooled event(s)", events.len()));
+                events
+            }
+            Err(e) => {
+                self.sdk_error(&format!("Failed to read the Sentry spool: {}", e).into());
+                vec![]
+            }
+        }
+    }
+
+    fn unspool(&self, e: &Event) {
+        if let Some(ref spool) = self.spool {
+            if let Err(err) = spool.remove(&e.event_id) {
+                self.sdk_error(&format!("Failed to remove spooled Sentry event {}: {}", e.event_id, err).into());
+            }
+        }
+    }
+
+    /// Reports due duplicate summaries, then retries held events, oldest
+    /// first, stopping at the first one that still can't be delivered.
+    pub fn flush_pending(&self) {
+        if let Some(ref dedupe) = self.dedupe {
+            self.send_summaries(dedupe);
+        }
+        loop {
+            let mut e = match lock(&self.pending).pop_front() {
+                Some(e) => e,
+                None => return,
+            };
+            if self.should_retry(&mut e, false) {
+                self.back_off();
+                lock(&self.pending).push_front(e);
+                return;
+            }
+            stats::pending(lock(&self.pending).len());
+        }
+    }
+
+    /// Holds off retries until the next flush.
+    fn back_off(&self) {
+        *lock(&self.retry_at) = self.flush_interval.map(|interval| self.clock.instant() + interval);
+    }
+
+    /// Posts `e` and tells whether it needs another attempt. Otherwise it's
+    /// done with, and removed from the spool. If the `first` attempt fails
+    /// during an outage, `e` is also written to syslog if configured.
+    fn should_retry(&self, e: &mut Event, first: bool) -> bool {
+        let result = self.post_pending(e);
+        let retryable = e.retry.pending.as_ref().map_or(false, |pending| !pending.is_empty());
+        let undelivered = match result {
+            Ok(0) if !self.is_enabled() => {
+                e.delivery.settle(DeliveryStatus::Dropped("disabled"));
+                false
+            }
+            Ok(0) if self.destinations_for(&e.logger).is_empty() => {
+                e.delivery.settle(DeliveryStatus::Dropped("undelivered"));
+                false
+            }
+            Err(Error(ErrorKind::Unserializable(_), _)) => {
+                stats::dropped("unserializable");
+                e.delivery.settle(DeliveryStatus::Dropped("unserializable"));
+                false
+            }
+            _ if retryable => true,
+            _ if e.retry.accepted => {
+                e.delivery.settle(DeliveryStatus::Sent);
+                false
+            }
+            _ => {
+                stats::dropped("rejected");
+                e.delivery.settle(DeliveryStatus::Dropped("rejected"));
+                false
+            }
+        };
+        if undelivered {
+            if first {
+                self.fall_back(e);
+            }
+            if self.retry {
+                return true;
+            }
+            stats::dropped("undelivered");
+            let now = self.clock.instant();
+            e.delivery.settle(if self.destinations_for(&e.logger).iter().all(|d| d.is_rate_limited(now)) {
+                DeliveryStatus::RateLimited
+            } else {
+                DeliveryStatus::Dropped("undelivered")
+            });
+        }
+        self.unspool(e);
+        false
+    }
+}
+
+#[cfg(feature = "spool-encryption")]
+fn spool(dir: PathBuf, settings: &Settings) -> Spool {
+    let spool = Spool::new(dir, settings.spool_max_files, settings.spool_max_bytes);
+    match settings.spool_key {
+        Some(ref key) => spool.with_key(key),
+        None => spool,
+    }
+}
+
+#[cfg(not(feature = "spool-encryption"))]
+fn spool(dir: PathBuf, settings: &Settings) -> Spool {
+    Spool::new(dir, settings.spool_max_files, settings.spool_max_bytes)
+}
+
+fn lock<'a, T>(mutex: &'a Mutex<T>) -> ::std::sync::MutexGuard<'a, T> {
+    match mutex.lock() {
+        Ok(guard) => guard,
+        Err(poisoned) => poisoned.into_inner(),
+    }
+}
+
+/// Sends `request` through hyper on a fresh tokio core.
+#[cfg(feature = "hyper-transport")]
+pub fn send(request: StoreRequest) -> Result<Response> {
+    let mut headers = Headers::new();
+    // already formatted into the worker's buffer, no need to parse it back
+    headers.set_raw(AuthHeader::NAME, request.auth.to_owned());
+    if let Some((username, password)) = request.basic {
+        headers.set(Authorization(Basic {
+            username: username.to_owned(),
+            password: Some(password.to_owned()),
+        }));
+    }
+    headers.set_raw("Content-Type", request.content_type.to_owned());
+    headers.set(UserAgent::new(request.user_agent.to_owned()));
+    for &(ref name, ref value) in request.headers {
+        headers.set_raw(name.clone(), value.clone());
+    }
+
+    let mut core = Core::new()?;
+    let handle = core.handle();
+    let tls = TlsConnector::builder()
+        .and_then(|builder| builder.build())
+        .map_err(|e| e.to_string())?;
+    let resolved = ResolvedConnector {
+        addrs: request.addrs.to_vec(),
+        timeout: request.connect_timeout,
+        handle: handle.clone(),
+    };
+    let connector = HttpsConnector::from((resolved, tls));
+    let client = Client::configure().connector(connector).build(&handle);
+
+    let uri = request.url.parse().map_err(|e: hyper::error::UriError| e.to_string())?;
+    let mut http_request = Request::new(Method::Post, uri);
+    *http_request.headers_mut() = headers;
+    // hyper takes ownership of the body, so this is the one copy left per send
+    http_request.set_body(request.body.to_vec());
+    let work = client.request(http_request)
+      .and_then(|res| {
+          let status = res.status().as_u16();
+          let retry_after = res.headers()
+              .get_raw("Retry-After")
+              .and_then(|raw| raw.one())
+              .and_then(|value| str::from_utf8(value).ok())
+              .and_then(|value| value.trim().parse().ok());
+          let date = res.headers()
+              .get_raw("Date")
+              .and_then(|raw| raw.one())
+              .and_then(|value| str::from_utf8(value).ok())
+              .map(|value| value.to_string());
+          res.body().concat2().map(move |b| (status, retry_after, date, b))
+      })
+      .map_err(|e| e.to_string())
+      .map(|(status, retry_after, date, b)| {
+          Response {
+              status: status,
+              retry_after: retry_after,
+              date: date,
+              body: String::from_utf8_lossy(&b).into_owned(),
+          }
+      });
+    let timeout = Timeout::new(request.timeout, &handle)?.map_err(|e| e.to_string());
+    let work = work.select2(timeout).then(|result| match result {
+        Ok(Either::A((response, _))) => Ok(response),
+        Ok(Either::B(_)) => Err("Sentry request timed out".to_string()),
+        Err(Either::A((e, _))) | Err(Either::B((e, _))) => Err(e),
+    });
+
+    Ok(core.run(work)?)
+}
+
+/// Connects to the addresses the transport resolved rather than having hyper
+/// resolve the URL host again.
+#[cfg(feature = "hyper-transport")]
+struct ResolvedConnector {
+    addrs: Vec<SocketAddr>,
+    timeout: Duration,
+    handle: Handle,
+}
+
+#[cfg(feature = "hyper-transport")]
+impl Service for ResolvedConnector {
+    type Request = Uri;
+    type Response = TcpStream;
+    type Error = io::Error;
+    type Future = Box<Future<Item = TcpStream, Error = io::Error>>;
+
+    /// Tries each address in turn until one accepts the connection in time.
+    fn call(&self, _uri: Uri) -> Self::Future {
+        let failed: Self::Future = Box::new(future::err(io::Error::new(io::ErrorKind::Other,
+                                                                       "Sentry host has no address")));
+        self.addrs.iter().fold(failed, |connecting, addr| {
+            let addr = *addr;
+            let timeout = self.timeout;
+            let handle = self.handle.clone();
+            Box::new(connecting.or_else(move |_| {
+                let connected = TcpStream::connect(&addr, &handle);
+                Timeout::new(timeout, &handle)
+                    .into_future()
+                    .and_then(move |timeout| {
+                        connected.select2(timeout).then(|result| match result {
+                            Ok(Either::A((stream, _))) => Ok(stream),
+                            Ok(Either::B(_)) => {
+                                Err(io::Error::new(io::ErrorKind::TimedOut, "connecting to Sentry timed out"))
+                            }
+                            Err(Either::A((e, _))) | Err(Either::B((e, _))) => Err(e),
+                        })
+                    })
+            }))
+        })
+    }
+}
+
+/// Sends `request` with a blocking HTTP/1.0 exchange on the calling thread.
+///
+/// HTTP/1.0 keeps the response free of chunked encoding, so the body is simply
+/// everything after the headers once the server closes the connection.
+#[cfg(not(feature = "hyper-transport"))]
+pub fn send(request: StoreRequest) -> Result<Response> {
+    let url = Url::parse(request.url).map_err(|e| e.to_string())?;
+    let host = url.host_str().ok_or("Sentry URL has no host")?.to_string();
+    let target = match url.query() {
+        Some(query) => format!("{}?{}", url.path(), query),
+        None => url.path().to_string(),
+    };
+    // virtual hosts on another port than the scheme's default need it in Host
+    let authority = match url.port() {
+        Some(port) => format!("{}:{}", host, port),
+        None => host.clone(),
+    };
+
+    let mut head = format!("POST {} HTTP/1.0\r\n\
+                        Host: {}\r\n\
+                        User-Agent: {}\r\n\
+                        X-Sentry-Auth: {}\r\n\
+                        Content-Type: {}\r\n\
+                        Content-Length: {}\r\n",
+                       target,
+                       authority,
+                       request.user_agent,
+                       request.auth,
+                       request.content_type,
+                       request.body.len());
+    if let Some((username, password)) = request.basic {
+        head.push_str(&format!("Authorization: Basic {}\r\n",
+                               base64::encode(format!("{}:{}", username, password).as_bytes())));
+    }
+    for &(ref name, ref value) in request.headers {
+        head.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    head.push_str("\r\n");
+
+    let stream = connect(request.addrs, request.connect_timeout)?;
+    stream.set_read_timeout(Some(request.timeout))?;
+    stream.set_write_timeout(Some(request.timeout))?;
+    let response = if url.scheme() == "https" {
+        tls_exchange(&host, stream, &head, request.body)?
+    } else {
+        exchange(stream, &head, request.body)?
+    };
+
+    parse_response(&response)
+}
+
+/// Tries each address in turn until one accepts the connection in time.
+#[cfg(not(feature = "hyper-transport"))]
+fn connect(addrs: &[SocketAddr], timeout: Duration) -> Result<TcpStream> {
+    let mut last_err = None;
+    for addr in addrs {
+        match TcpStream::connect_timeout(addr, timeout) {
+            Ok(stream) => return Ok(stream),
+            Err(err) => last_err = Some(err),
+        }
+    }
+    Err(match last_err {
+        Some(err) => err.into(),
+        None => "Sentry host has no address".into(),
+    })
+}
+
+/// The rustls configuration, built by the first HTTPS request.
+#[cfg(all(not(feature = "hyper-transport"), feature = "rustls-tls"))]
+static TLS_CONFIG: Mutex<Option<Arc<ClientConfig>>> = Mutex::new(None);
+
+/// Trusts the bundled Mozilla roots so static binaries need no system
+/// certificate store.
+#[cfg(all(not(feature = "hyper-transport"), feature = "rustls-tls"))]
+fn tls_config() -> Result<Arc<ClientConfig>> {
+    let mut config = lock(&TLS_CONFIG);
+    if let Some(ref config) = *config {
+        return Ok(config.clone());
+    }
+    let mut roots = RootCertStore::empty();
+    roots.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned());
+    let built = Arc::new(ClientConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+        .with_safe_default_protocol_versions()
+        .map_err(|e| e.to_string())?
+        .with_root_certificates(roots)
+        .with_no_client_auth());
+    *config = Some(built.clone());
+    Ok(built)
+}
+
+/// `exchange` over TLS through rustls.
+#[cfg(all(not(feature = "hyper-transport"), feature = "rustls-tls"))]
+fn tls_exchange(host: &str, stream: TcpStream, head: &str, body: &[u8]) -> Result<Vec<u8>> {
+    let name = ServerName::try_from(host.to_string()).map_err(|e| e.to_string())?;
+    let connection = ClientConnection::new(tls_config()?, name).map_err(|e| e.to_string())?;
+    exchange(StreamOwned::new(connection, stream), head, body)
+}
+
+#[cfg(all(not(feature = "hyper-transport"), feature = "native-tls", not(feature = "rustls-tls")))]
+fn tls_exchange(host: &str, stream: TcpStream, head: &str, body: &[u8]) -> Result<Vec<u8>> {
+    let connector = TlsConnector::builder()
+        .and_then(|builder| builder.build())
+        .map_err(|e| e.to_string())?;
+    let stream = connector.connect(host, stream).map_err(|e| e.to_string())?;
+    exchange(stream, head, body)
+}
+
+#[cfg(not(feature = "hyper-transport"))]
+fn parse_response(response: &[u8]) -> Result<Response> {
+    let (head, body) = match head_end(response) {
+        Some(end) => (&response[..end], &response[end + 4..]),
+        None => (response, &[][..]),
+    };
+    // only the head has to be text, the body is kept for diagnostics
+    let head = String::from_utf8_lossy(head);
+    let status = head.split("\r\n").next()
+        .and_then(|line| line.split(' ').nth(1))
+        .and_then(|code| code.parse().ok())
+        .ok_or("Malformed HTTP response from Sentry")?;
+    let header = |name: &str| header(&head, name);
+    Ok(Response {
+        status: status,
+        retry_after: header("retry-after").and_then(|value| value.parse().ok()),
+        date: header("date").map(|value| value.to_string()),
+        body: String::from_utf8_lossy(body).into_owned(),
+    })
+}
+
+/// Where the head of `response` ends, at its blank line.
+#[cfg(not(feature = "hyper-transport"))]
+fn head_end(response: &[u8]) -> Option<usize> {
+    response.windows(4).position(|window| window == b"\r\n\r\n")
+}
+
+/// The value of the header `name` in the response head `head`.
+#[cfg(not(feature = "hyper-transport"))]
+fn header<'a>(head: &'a str, name: &str) -> Option<&'a str> {
+    head.split("\r\n")
+        .skip(1)
+        .filter_map(|line| {
+            let mut parts = line.splitn(2, ':');
+            match (parts.next(), parts.next()) {
+                (Some(header), Some(value)) if header.trim().eq_ignore_ascii_case(name) => Some(value.trim()),
+                _ => None,
+            }
+        })
+        .next()
+}
+
+/// Sends the request and reads the response, up to its `Content-Length` if
+/// it has one, else until the server closes the connection. Servers often
+/// close TLS connections without a close_notify once they have answered, so
+/// an unexpected end of the stream after the head ends the response instead
+/// of failing the request, which would send the event again.
+#[cfg(not(feature = "hyper-transport"))]
+pub fn exchange<S: Read + Write>(mut stream: S, head: &str, body: &[u8]) -> Result<Vec<u8>> {
+    stream.write_all(head.as_bytes())?;
+    stream.write_all(body)?;
+    stream.flush()?;
+
+    let mut response = Vec::new();
+    let mut chunk = [0; 4096];
+    loop {
+        let read = match stream.read(&mut chunk) {
+            Ok(read) => read,
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(ref e) if e.kind() == io::ErrorKind::UnexpectedEof && head_end(&response).is_some() => break,
+            Err(e) => return Err(e.into()),
+        };
+        if read == 0 {
+            break;
+        }
+        response.extend_from_slice(&chunk[..read]);
+        if let Some(end) = head_end(&response) {
+            let length = header(&String::from_utf8_lossy(&response[..end]), "content-length")
+                .and_then(|value| value.parse::<usize>().ok());
+            if let Some(length) = length {
+                if response.len() >= end + 4 + length {
+                    response.truncate(end + 4 + length);
+                    break;
+                }
+            }
+        }
+    }
+    Ok(response)
+}
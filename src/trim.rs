@@ -0,0 +1,180 @@
+//! Keeps outgoing events under Sentry's payload limit.
+
+use serde_json::{self, Value};
+
+use errors::*;
+use internal;
+use stats;
+use timestamp::{self, Timestamp};
+use Event;
+
+/// Largest serialized event the store endpoint accepts.
+pub const MAX_EVENT_SIZE: usize = 1024 * 1024;
+
+const MAX_EXTRA_VALUE_LENGTH: usize = 512;
+const MAX_FRAMES: usize = 50;
+const MAX_MESSAGE_LENGTH: usize = 8192;
+
+/// Serializes `e` into `body`, trimming it progressively until it fits in
+/// `max_size` bytes.
+///
+/// Breadcrumbs are dropped first, then long extra values are truncated, then
+/// the stack trace is capped to its innermost and outermost frames, then extra
+/// is dropped and finally the message is truncated, each noted in the event's
+/// `_meta` the way Sentry annotates data it trimmed itself, and counted in the
+/// `sentry_trimmed_total` metric. Fails if the event is still too large after
+/// that. With `epoch_timestamps`, timestamps are sent
+/// as seconds since the UNIX epoch; they are moved by `clock_skew` seconds, how
+/// far the local clock is behind the server's.
+pub fn serialize_within(e: &mut Event,
+                        max_size: usize,
+                        epoch_timestamps: bool,
+                        clock_skew: i64,
+                        body: &mut Vec<u8>)
+                        -> Result<()> {
+    serialize_into(e, epoch_timestamps, clock_skew, body)?;
+    if body.len() <= max_size {
+        return Ok(());
+    }
+    let original_size = body.len();
+
+    // what each step removed, and how many of them
+    let steps: [(&str, fn(&mut Event) -> usize); 5] = [("breadcrumbs", drop_breadcrumbs),
+                                                       ("extra_values", truncate_extra),
+                                                       ("frames", cap_frames),
+                                                       ("extra", drop_extra),
+                                                       ("message", truncate_message)];
+    for &(trimmed, step) in steps.iter() {
+        let count = step(e);
+        if count > 0 {
+            warn!(target: internal::TARGET,
+                  "Sentry event of {} bytes exceeds {} bytes: trimmed {} {}",
+                  original_size,
+                  max_size,
+                  count,
+                  trimmed);
+            stats::trimmed(trimmed, count);
+            serialize_into(e, epoch_timestamps, clock_skew, body)?;
+            if body.len() <= max_size {
+                return Ok(());
+            }
+        }
+    }
+
+    Err(format!("Sentry event of {} bytes is still {} bytes after trimming, exceeding {} bytes",
+                original_size,
+                body.len(),
+                max_size)
+        .into())
+}
+
+fn serialize_into(e: &Event, epoch_timestamps: bool, clock_skew: i64, body: &mut Vec<u8>) -> Result<()> {
+    body.clear();
+    if !epoch_timestamps && clock_skew == 0 {
+        return Ok(serde_json::to_writer(body, e)?);
+    }
+    let serialize = |timestamp: &Timestamp| {
+        let timestamp = timestamp::shift(timestamp, clock_skew);
+        if epoch_timestamps {
+            json!(timestamp::epoch(&timestamp))
+        } else {
+            json!(timestamp::format(&timestamp))
+        }
+    };
+    let mut json = serde_json::to_value(e)?;
+    json["timestamp"] = serialize(&e.timestamp);
+    if let Some(ref start) = e.start_timestamp {
+        json["start_timestamp"] = serialize(start);
+    }
+    if let Some(spans) = json.get_mut("spans").and_then(Value::as_array_mut) {
+        for (span, original) in spans.iter_mut().zip(&e.spans) {
+            span["start_timestamp"] = serialize(&original.start_timestamp);
+            span["timestamp"] = serialize(&original.timestamp);
+        }
+    }
+    if let Some(breadcrumbs) = json.pointer_mut("/breadcrumbs/values").and_then(Value::as_array_mut) {
+        for (breadcrumb, original) in breadcrumbs.iter_mut().zip(&e.breadcrumbs) {
+            breadcrumb["timestamp"] = serialize(original.timestamp());
+        }
+    }
+    Ok(serde_json::to_writer(body, &json)?)
+}
+
+/// Truncates `s` to at most `max` bytes on a char boundary, marking the cut.
+pub fn truncate(s: &mut String, max: usize) -> bool {
+    if s.len() <= max {
+        return false;
+    }
+    let mut end = max.saturating_sub(3);
+    while !s.is_char_boundary(end) {
+        end -= 1;
+    }
+    s.truncate(end);
+    s.push_str("...");
+    true
+}
+
+/// The `_meta` annotation of a collection that had `len` items.
+pub fn removed(len: usize) -> Value {
+    json!({ "": { "len": len } })
+}
+
+/// The `_meta` annotation of a string of `len` bytes truncated with
+/// `truncate` to `truncated`, ex: "abc...".
+pub fn truncated(len: usize, truncated: &str) -> Value {
+    let end = truncated.len();
+    json!({ "": { "rem": [["!limit", "s", end - 3, end]], "len": len } })
+}
+
+fn drop_breadcrumbs(e: &mut Event) -> usize {
+    let count = e.breadcrumbs.len();
+    if count > 0 {
+        e.breadcrumbs.clear();
+        e.meta["breadcrumbs"]["values"] = removed(count);
+    }
+    count
+}
+
+fn truncate_extra(e: &mut Event) -> usize {
+    let mut count = 0;
+    for (key, value) in e.extra.iter_mut() {
+        let len = value.len();
+        if truncate(value, MAX_EXTRA_VALUE_LENGTH) {
+            e.meta["extra"][key] = truncated(len, value);
+            count += 1;
+        }
+    }
+    count
+}
+
+fn cap_frames(e: &mut Event) -> usize {
+    match e.stacktrace {
+        Some(ref mut stacktrace) if stacktrace.frames.len() > MAX_FRAMES => {
+            let len = stacktrace.frames.len();
+            stacktrace.frames.drain(MAX_FRAMES / 2..len - MAX_FRAMES / 2);
+            e.meta["stacktrace"]["frames"] = removed(len);
+            len - MAX_FRAMES
+        }
+        _ => 0,
+    }
+}
+
+fn drop_extra(e: &mut Event) -> usize {
+    let count = e.extra.len();
+    if count > 0 {
+        e.extra.clear();
+        // replaces the annotations of the values truncated before
+        e.meta["extra"] = removed(count);
+    }
+    count
+}
+
+fn truncate_message(e: &mut Event) -> usize {
+    let len = e.message.len();
+    if truncate(&mut e.message, MAX_MESSAGE_LENGTH) {
+        e.meta["message"] = truncated(len, &e.message);
+        1
+    } else {
+        0
+    }
+}
@@ -0,0 +1,61 @@
+//! Checks of outgoing events against the Sentry event schema, run in debug
+//! mode to catch mistakes the server would silently drop or mangle.
+
+use tags::{MAX_TAG_KEY_LENGTH, MAX_TAG_VALUE_LENGTH};
+use Event;
+
+const LEVELS: [&'static str; 5] = ["fatal", "error", "warning", "info", "debug"];
+const MAX_MESSAGE_LENGTH: usize = 8192;
+const MAX_CULPRIT_LENGTH: usize = 200;
+const MAX_RELEASE_LENGTH: usize = 200;
+const MAX_ENVIRONMENT_LENGTH: usize = 64;
+
+/// Describes each way `e` violates the schema.
+pub fn violations(e: &Event) -> Vec<String> {
+    let mut violations = vec![];
+    if e.event_id.len() != 32 || !e.event_id.chars().all(|c| c.is_digit(16) && !c.is_uppercase()) {
+        violations.push(format!("event_id {:?} isn't 32 lowercase hex characters", e.event_id));
+    }
+    if !LEVELS.contains(&&e.level[..]) {
+        violations.push(format!("level {:?} isn't one of {}", e.level, LEVELS.join(", ")));
+    }
+    // transactions are named rather than described
+    if e.message.is_empty() && !e.is_transaction() {
+        violations.push("message is empty".to_string());
+    }
+    check_length(&mut violations, "message", &e.message, MAX_MESSAGE_LENGTH);
+    if let Some(ref culprit) = e.culprit {
+        check_length(&mut violations, "culprit", culprit, MAX_CULPRIT_LENGTH);
+    }
+    if let Some(ref release) = e.release {
+        check_length(&mut violations, "release", release, MAX_RELEASE_LENGTH);
+        if release.trim().is_empty() || &release[..] == "." || &release[..] == ".." ||
+           release.contains(|c| c == '\n' || c == '\t' || c == '/' || c == '\\') {
+            violations.push(format!("release {:?} isn't a valid release name", release));
+        }
+    }
+    if let Some(ref environment) = e.environment {
+        check_length(&mut violations, "environment", environment, MAX_ENVIRONMENT_LENGTH);
+        if environment.contains(|c| c == '\n' || c == '/') {
+            violations.push(format!("environment {:?} contains a line break or slash", environment));
+        }
+    }
+    for (key, value) in &e.tags {
+        check_length(&mut violations, "tag key", key, MAX_TAG_KEY_LENGTH);
+        check_length(&mut violations, &format!("tag {:?}", key), value, MAX_TAG_VALUE_LENGTH);
+        if key.contains('\n') || value.contains('\n') {
+            violations.push(format!("tag {:?} contains a line break", key));
+        }
+    }
+    if !e.fingerprint.is_empty() && e.fingerprint.iter().all(|part| part.is_empty()) {
+        violations.push("fingerprint has only empty parts".to_string());
+    }
+    violations
+}
+
+fn check_length(violations: &mut Vec<String>, field: &str, value: &str, max_length: usize) {
+    let length = value.chars().count();
+    if length > max_length {
+        violations.push(format!("{} is {} characters long, exceeding {}", field, length, max_length));
+    }
+}
@@ -0,0 +1,177 @@
+//! A companion process reporting the abnormal exit of the one it watches.
+//!
+//! In-process hooks can't report a SIGKILL, an OOM kill or an abort. The
+//! watched process instead streams its latest crash event to a watchdog child
+//! over a pipe, and tells it when it exits cleanly; if the pipe closes without
+//! that, the watchdog sends the last event it received.
+//!
+//! The first line through the pipe tells the watchdog where to report to,
+//! rather than its environment, which other users' processes may read.
+
+use std::env;
+use std::io::{self, BufRead, Write};
+#[cfg(unix)]
+use std::os::unix::process::CommandExt;
+use std::process::{self, Child, ChildStdin, Command, Stdio};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use serde_json;
+
+use errors::*;
+use {scope, timestamp, uuid, Event, EventDefaults, Sentry, SentryCredential, Settings};
+
+/// Set on the watchdog process, to tell it from the watched one.
+const WATCHDOG_VAR: &'static str = "SENTRY_WATCHDOG";
+
+/// Handle of the watchdog of this process, see `Sentry::spawn_watchdog`.
+pub struct Watchdog {
+    child: Child,
+    stdin: Mutex<ChildStdin>,
+    defaults: Arc<EventDefaults>,
+}
+
+impl Watchdog {
+    pub fn spawn(mut command: Command, sentry: &Sentry) -> Result<Watchdog> {
+        let destination = destination(sentry)?;
+        // out of the terminal's process group, so a ctrl-c doesn't kill the
+        // watchdog before it can report
+        #[cfg(unix)]
+        command.process_group(0);
+        let mut child = command.env(WATCHDOG_VAR, "1").stdin(Stdio::piped()).spawn()?;
+        let stdin = child.stdin.take().ok_or("watchdog has no stdin")?;
+        let watchdog = Watchdog {
+            child: child,
+            stdin: Mutex::new(stdin),
+            defaults: sentry.defaults.clone(),
+        };
+        watchdog.write(&destination)?;
+        watchdog.checkpoint()?;
+        Ok(watchdog)
+    }
+
+    /// Sends the scope of the calling thread to the watchdog, as the context of
+    /// the crash event it reports if this process dies. Call it whenever the
+    /// scope changes meaningfully, e.g. when a request or job starts.
+    pub fn checkpoint(&self) -> Result<()> {
+        // created as an error so the thread dump of fatal events is left out,
+        // since these threads wouldn't be the ones of the crash
+        let mut e = self.defaults.event("watchdog",
+                                        "error",
+                                        &format!("Process {} exited abnormally", process::id()));
+        scope::with_current(|scope| e.merge_from(scope));
+        e.set_level("fatal");
+        e.set_extra("watchdog.pid".to_string(), process::id().to_string());
+        let line = format!("event {}\n", e.to_json()?);
+        self.write(&line)
+    }
+
+    /// Tells the watchdog this process exits cleanly and waits for it to end.
+    /// Without it, every exit is reported as a crash.
+    pub fn shutdown(mut self) -> Result<()> {
+        self.write("exit\n")?;
+        self.child.wait()?;
+        Ok(())
+    }
+
+    fn write(&self, line: &str) -> Result<()> {
+        let mut stdin = match self.stdin.lock() {
+            Ok(guard) => guard,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        stdin.write_all(line.as_bytes())?;
+        stdin.flush()?;
+        Ok(())
+    }
+}
+
+/// The line telling the watchdog where to report to: the first credential of
+/// `sentry`, and its tunnel or endpoint template, so the watchdog reaches the
+/// same URL, with the same scheme and port.
+pub fn destination(sentry: &Sentry) -> Result<String> {
+    let credential = match sentry.credentials().into_iter().next() {
+        Some(credential) => credential,
+        None => bail!("Sentry has no credential for the watchdog to report to"),
+    };
+    let (tunnel, endpoint_template) = sentry.transport.endpoint();
+    let destination = json!({
+        "key": credential.key,
+        "secret": credential.secret,
+        "host": credential.host,
+        "project_id": credential.project_id,
+        "tunnel": tunnel,
+        "endpoint_template": endpoint_template,
+    });
+    Ok(format!("destination {}\n", destination))
+}
+
+/// The client the watchdog reports with, from the line of `destination`. The
+/// credential is taken as the watched process had it, whether it came from
+/// `SentryCredential::new` or from a DSN, rather than checked again.
+pub fn reporter(line: &str) -> Result<Sentry> {
+    if !line.starts_with("destination ") {
+        bail!("watchdog got no destination");
+    }
+    let destination: serde_json::Value = serde_json::from_str(&line["destination ".len()..])?;
+    let field = |name: &str| destination[name].as_str().unwrap_or("").to_string();
+    let credential = SentryCredential {
+        key: field("key"),
+        secret: field("secret"),
+        host: field("host"),
+        project_id: field("project_id"),
+    };
+    if credential.key.is_empty() || credential.host.is_empty() || credential.project_id.is_empty() {
+        bail!("watchdog got an incomplete destination");
+    }
+    let settings = Settings {
+        tunnel: destination["tunnel"].as_str().map(str::to_string),
+        endpoint_template: destination["endpoint_template"].as_str().map(str::to_string),
+        ..Settings::default()
+    };
+    Ok(Sentry::from_settings(settings, credential))
+}
+
+/// Reads the watched process' messages until its exit, returning the event to
+/// report unless it exited cleanly.
+pub fn watch<R: BufRead>(input: R) -> Option<Event> {
+    let mut last = None;
+    for line in input.lines() {
+        let line = match line {
+            Ok(line) => line,
+            Err(_) => break,
+        };
+        if line == "exit" {
+            return None;
+        }
+        if line.starts_with("event ") {
+            if let Ok(e) = Event::from_json(&line["event ".len()..]) {
+                last = Some(e);
+            }
+        }
+    }
+    last.map(|mut e| {
+        e.event_id = uuid::Uuid::new_v4().simple().to_string();
+        e.timestamp = timestamp::now();
+        e
+    })
+}
+
+/// Runs the watchdog and exits if this process was spawned as one by
+/// `Sentry::spawn_watchdog`, and returns otherwise. Call it first thing in
+/// `main`.
+pub fn watchdog_main() {
+    if env::var_os(WATCHDOG_VAR).is_none() {
+        return;
+    }
+    let stdin = io::stdin();
+    let mut stdin = stdin.lock();
+    let mut line = String::new();
+    let sentry = stdin.read_line(&mut line).map_err(Error::from).and_then(|_| reporter(line.trim_end()));
+    if let Some(e) = watch(stdin) {
+        if let Ok(sentry) = sentry {
+            sentry.log_event(e);
+            sentry.flush(Duration::from_secs(5));
+        }
+    }
+    process::exit(0);
+}